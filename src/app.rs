@@ -1,77 +1,257 @@
 use eframe::egui::{self, Vec2};
 use crate::color_selector::ColorSelector;
+use crate::palette::ColorPalette;
 use crate::primitives::*;
 use crate::engine::*;
 use crate::floating_window::FloatingWindow;
-use crate::paint_object::{freehand_curve::*, straight_line::*, picture::*};
+use crate::paint_object::{freehand_curve::*, straight_line::*, picture::*, shape::*};
 use crate::egui_painter::*;
+use crate::properties_panel;
+use crate::autosave::Autosave;
+use crate::scene_file;
+use crate::toasts::Toasts;
+use crate::clipboard::{Clipboard, ClipboardContent};
+use crate::raster_painter::RasterPainter;
+use crate::svg_painter::SvgPainter;
 
 
 pub const WINDOW_INIT_SIZE: Vec2 = Vec2::new(1000.0, 600.0);
 pub const WINDOW_MIN_SIZE:  Vec2 = Vec2::new(300.0, 200.0);
 pub const UI_SCALE: f32          = 1.5;
-pub const NAME: &'static str     = "PiciPaint";
+pub const NAME: &str              = "PiciPaint";
+// larger selections are scaled down when they are copied as a picture, so that they fit into memory
+const MAXIMUM_COPIED_PIXELS: f32  = 4096.0 * 4096.0;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ClipboardAction {
+    Paste,
+    CopyAsPng,
+    CopyAsSvg,
+}
 
 
 pub struct App {
     engine: Engine<EguiPainter, egui::ImageSource<'static>>,
     stroke: Stroke<WorldSpace>,
+    fill: Fill,
+    fill_window: FloatingWindow,
     bg_color: Color,
     fg_color_selector: ColorSelector,
     bg_color_selector: ColorSelector,
-    error_window: FloatingWindow,
-    error_msg: String,
+    color_palette: ColorPalette,
+    toasts: Toasts,
+    autosave: Autosave,
+    // the autosaved scene of a crashed session, until the user decides whether to restore it
+    recovered_scene: Option<String>,
+    clipboard: Clipboard,
+    // set when ctrl+V was seen as a paste event, so that releasing V doesn't paste a second time
+    paste_shortcut_was_handled: bool,
 }
 
 impl App {
     pub fn new(_context: &eframe::CreationContext) -> Self {
+        let (autosave, recovered_scene) = Autosave::start();
         Self {
             engine: Engine::new(vec![
                 Box::new(FreehandCurveTool::default()),
                 Box::new(StraghtLineTool::default()),
                 Box::new(PictureTool::default()),
+                Box::new(ShapeTool::new(ShapeKind::Rectangle)),
+                Box::new(ShapeTool::new(ShapeKind::Ellipse)),
+                Box::new(ShapeTool::new(ShapeKind::Polygon)),
             ]),
             stroke: Stroke::new(Color::from_rgb(0, 0, 200), Number::<WorldSpace>::new(2.0)),
+            fill: Fill::None,
+            fill_window: FloatingWindow::new("fill"),
             bg_color: Color::from_rgb(255, 255, 255),
             fg_color_selector: ColorSelector::new("Foreground color"),
             bg_color_selector: ColorSelector::new("Background color"),
-            error_window: FloatingWindow::new("error"),
-            error_msg: String::new(),
+            color_palette: ColorPalette::load(),
+            toasts: Toasts::default(),
+            autosave,
+            recovered_scene,
+            clipboard: Clipboard::default(),
+            paste_shortcut_was_handled: false,
         }
     }
 }
 
-impl eframe::App for App {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        ctx.set_pixels_per_point(UI_SCALE);
+impl App {
+    // Takes over the mouse while one of the color selectors is picking a color from the canvas.
+    // Left click picks, right click and escape cancel.
+    fn update_eyedropper(&mut self, user_input: UserInput) -> UserInput {
+        let picked_color = match user_input {
+            UserInput::MouseClick { position, button: MouseButton::Left, .. } => Some(Some(self.engine.color_at(position, self.bg_color))),
+            UserInput::MouseClick { .. } | UserInput::DeselectAll             => Some(None),
+            _                                                                 => None,
+        };
+        if let Some(picked_color) = picked_color {
+            if self.fg_color_selector.eyedropper_is_active {
+                self.fg_color_selector.finish_eyedropper(&mut self.stroke.color, picked_color);
+            }
+            else {
+                self.bg_color_selector.finish_eyedropper(&mut self.bg_color, picked_color);
+            }
+        }
+
+        match user_input {
+            // the canvas must not react to the clicks of the eyedropper
+            UserInput::MouseClick { .. } | UserInput::MouseMove { .. } | UserInput::DeselectAll => UserInput::Nothing,
+            _                                                                                => user_input,
+        }
+    }
+}
 
-        let dropped_pictures = ctx.input(|input| {
-            let offset = Vector2::new(10.0, 10.0);
-            let mut pictures = Vec::new();
-            for (i, dropped_file) in input.raw.dropped_files.iter().enumerate() {
-                match Picture::from_dropped_file(dropped_file, offset * (i as f32)) {
-                    Ok(Some(picture)) => pictures.push(picture),
-                    Ok(None)          => {/*This isn't a picture, just skip it*/},
-                    Err(error_msg)    => return Err(error_msg),
+impl App {
+    // The autosaved scene is only deleted with the discard button. The dialog can't be closed in any other way,
+    // and if the app exits while it's open then the scene is offered again on the next start.
+    fn update_recovery(&mut self, ctx: &egui::Context) {
+        if self.recovered_scene.is_none() {
+            return;
+        }
+        let mut restore = false;
+        let mut discard = false;
+        // clicking the backdrop or pressing escape would close it, `should_close` is ignored on purpose
+        egui::Modal::new(egui::Id::new("recovery")).show(ctx, |ui| {
+            ui.heading("Restore drawing");
+            ui.label(format!("{} didn't exit normally last time. Do you want to restore the autosaved drawing?", NAME));
+            ui.horizontal(|ui| {
+                restore = ui.button("restore").clicked();
+                discard = ui.button("discard").clicked();
+            });
+        });
+
+        if !restore && !discard {
+            return;
+        }
+        let Some(scene) = self.recovered_scene.take() else {
+            return;
+        };
+        if restore {
+            match scene_file::load_objects(&scene) {
+                Ok(objects) => self.engine.replace_objects(objects),
+                Err(err)    => self.toasts.error("Could not restore the drawing.", &err),
+            }
+        }
+        else {
+            self.autosave.discard_recovery_file();
+        }
+        self.autosave.is_paused = false;
+    }
+}
+
+impl App {
+    // Ctrl+V only arrives as a paste event when there is text in the clipboard, not when there is just an image,
+    // so releasing V while ctrl is down counts as pasting as well.
+    fn clipboard_shortcut(&mut self, ctx: &egui::Context) -> Option<ClipboardAction> {
+        if ctx.wants_keyboard_input() {
+            return None;
+        }
+        let mut action = None;
+        ctx.input(|input| {
+            for event in input.events.iter() {
+                match event {
+                    egui::Event::Copy if input.modifiers.shift => action = Some(ClipboardAction::CopyAsSvg),
+                    egui::Event::Copy                          => action = Some(ClipboardAction::CopyAsPng),
+                    egui::Event::Paste(_)                      => {
+                        action = Some(ClipboardAction::Paste);
+                        self.paste_shortcut_was_handled = true;
+                    },
+                    // resets the flag for the next time ctrl+V is pressed
+                    egui::Event::Key { key: egui::Key::V, pressed: false, modifiers, .. } if modifiers.command && !std::mem::take(&mut self.paste_shortcut_was_handled) => {
+                        action = Some(ClipboardAction::Paste);
+                    },
+                    _ => {},
                 }
             }
-            return Ok(pictures);
         });
+        action
+    }
 
-        match dropped_pictures {
-            Ok(pictures) => {
-                for picture in pictures {
-                    self.engine.add_object(picture);
+    // `position` is where the mouse is on the canvas, pasted pictures go to the center of the view without it.
+    fn run_clipboard_action(&mut self, action: ClipboardAction, position: Option<Vector2<ScreenSpace>>) {
+        match action {
+            ClipboardAction::Paste     => self.paste(position),
+            ClipboardAction::CopyAsPng => self.copy_selection(false),
+            ClipboardAction::CopyAsSvg => self.copy_selection(true),
+        }
+    }
+
+    fn paste(&mut self, position: Option<Vector2<ScreenSpace>>) {
+        let top_left = self.engine.world_position(position);
+        match self.clipboard.paste() {
+            Ok(ClipboardContent::Image(image)) => self.engine.add_object(Picture::from_pasted_image(image, top_left)),
+            Ok(ClipboardContent::Files(paths)) => {
+                let offset = Vector2::new(10.0, 10.0);
+                for (i, path) in paths.iter().enumerate() {
+                    match Picture::from_file(path, top_left + offset * (i as f32)) {
+                        Some(picture) => self.engine.add_object(picture),
+                        None          => self.toasts.warning(format!("Skipped \"{}\", it is not a picture in a supported format.", path.display())),
+                    }
                 }
             },
-            Err(error_msg) => {
-                self.error_msg = error_msg;
-                self.error_window.is_open = true;
-            },
+            Ok(ClipboardContent::Nothing)      => self.toasts.warning("There is no picture in the clipboard."),
+            Err(err)                           => self.toasts.error("Could not paste.", &err),
+        }
+    }
+
+    fn copy_selection(&mut self, as_svg: bool) {
+        let Some(rect) = self.engine.export_rect() else {
+            self.toasts.warning("Select something to copy first.");
+            return;
+        };
+        let (width, height) = (rect.width().value, rect.height().value);
+        let result = if as_svg {
+            let mut painter = SvgPainter::new(width.ceil() as u32, height.ceil() as u32);
+            self.engine.export_selection(&mut painter, rect, 1.0);
+            self.clipboard.copy_text(painter.finish())
+        }
+        else {
+            let scale = (MAXIMUM_COPIED_PIXELS / (width * height)).sqrt().min(1.0);
+            let mut painter = RasterPainter::new((width * scale).ceil() as u32, (height * scale).ceil() as u32);
+            self.engine.export_selection(&mut painter, rect, scale);
+            self.clipboard.copy_image(&painter.image)
+        };
+        match result {
+            Ok(())   => self.toasts.info(if as_svg { "Copied the selection as SVG." } else { "Copied the selection as PNG." }),
+            Err(err) => self.toasts.error("Could not copy the selection.", &err),
+        }
+    }
+}
+
+impl eframe::App for App {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.autosave.stop();
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        ctx.set_pixels_per_point(UI_SCALE);
+
+        // every file is reported on its own, one bad file doesn't prevent inserting the others
+        let dropped_files = ctx.input(|input| input.raw.dropped_files.clone());
+        let offset = Vector2::new(10.0, 10.0);
+        for (i, dropped_file) in dropped_files.iter().enumerate() {
+            let name = dropped_file.path.as_ref()
+                .and_then(|path| path.file_name())
+                .map_or_else(|| dropped_file.name.clone(), |name| name.to_string_lossy().into_owned());
+            match Picture::from_dropped_file(dropped_file, offset * (i as f32)) {
+                Ok(Some(picture)) => {
+                    self.engine.add_object(picture);
+                    self.toasts.info(format!("Loading \"{}\"...", name));
+                },
+                Ok(None)          => self.toasts.warning(format!("Skipped \"{}\", it is not a picture in a supported format.", name)),
+                Err(err)          => self.toasts.error(format!("Could not insert \"{}\".", name), &err),
+            }
         }
 
+        let modal_dialog_is_open = self.bg_color_selector.window.is_open || self.fg_color_selector.window.is_open || self.fill_window.is_open || self.recovered_scene.is_some();
+
+        properties_panel::show(ctx, &mut self.engine, !modal_dialog_is_open);
+
+        let mut clipboard_action = None;
+
         egui::CentralPanel::default().show(ctx, |ui| {
-            let modal_dialog_is_open = self.error_window.is_open || self.bg_color_selector.window.is_open || self.fg_color_selector.window.is_open;
             if modal_dialog_is_open {
                 ui.disable();
             }
@@ -97,12 +277,106 @@ impl eframe::App for App {
 
                 ui.toggle_value(&mut self.fg_color_selector.window.is_open, "fg color");
                 ui.toggle_value(&mut self.bg_color_selector.window.is_open, "bg color");
-                ui.add(egui::Slider::new(&mut self.stroke.thickness.value, 0.5..=10.0)).on_hover_ui_at_pointer(|ui| {
+                ui.toggle_value(&mut self.fill_window.is_open, "fill");
+                ui.add(egui::Slider::new(&mut self.stroke.thickness.value, 0.5..=MAXIMUM_STROKE_THICKNESS.value)).on_hover_ui_at_pointer(|ui| {
                     ui.label("line thickness");
                 });
+                // combo boxes close a surrounding menu when their popup is clicked, so they are placed directly in the toolbar
+                properties_panel::dash_pattern_ui(ui, "toolbar dash", &mut self.stroke.dash);
+                properties_panel::line_cap_ui(ui, "toolbar cap", &mut self.stroke.cap);
+                properties_panel::line_join_ui(ui, "toolbar join", &mut self.stroke.join);
                 if ui.button("clip").clicked() {
                     self.engine.start_clipping();
                 }
+
+                ui.separator();
+
+                ui.menu_button("edit", |ui| {
+                    let actions = [
+                        (ClipboardAction::Paste,     "paste (ctrl+V)"),
+                        (ClipboardAction::CopyAsPng, "copy as PNG (ctrl+C)"),
+                        (ClipboardAction::CopyAsSvg, "copy as SVG (ctrl+shift+C)"),
+                    ];
+                    for (action, label) in actions {
+                        if ui.button(label).clicked() {
+                            clipboard_action = Some(action);
+                            ui.close_menu();
+                        }
+                    }
+                });
+                ui.menu_button("align", |ui| {
+                    let alignments = [
+                        (Alignment::Left,             "left (alt+left)"),
+                        (Alignment::Right,            "right (alt+right)"),
+                        (Alignment::Top,              "top (alt+up)"),
+                        (Alignment::Bottom,           "bottom (alt+down)"),
+                        (Alignment::CenterHorizontal, "center horizontally (alt+H)"),
+                        (Alignment::CenterVertical,   "center vertically (alt+V)"),
+                    ];
+                    for (alignment, label) in alignments {
+                        if ui.button(label).clicked() {
+                            self.engine.align_selected_objects(alignment);
+                            ui.close_menu();
+                        }
+                    }
+                    ui.separator();
+                    if ui.button("distribute horizontally (alt+shift+H)").clicked() {
+                        self.engine.distribute_selected_objects(Axis::Horizontal);
+                        ui.close_menu();
+                    }
+                    if ui.button("distribute vertically (alt+shift+V)").clicked() {
+                        self.engine.distribute_selected_objects(Axis::Vertical);
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("flip", |ui| {
+                    if ui.button("flip horizontally (H)").clicked() {
+                        self.engine.flip_selected_objects(Axis::Horizontal);
+                        ui.close_menu();
+                    }
+                    if ui.button("flip vertically (V)").clicked() {
+                        self.engine.flip_selected_objects(Axis::Vertical);
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("rotate", |ui| {
+                    if ui.button("rotate right (R)").clicked() {
+                        self.engine.rotate_selected_objects(Rotation::Clockwise);
+                        ui.close_menu();
+                    }
+                    if ui.button("rotate left (shift+R)").clicked() {
+                        self.engine.rotate_selected_objects(Rotation::CounterClockwise);
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("style", |ui| {
+                    if ui.button("copy style (alt+C)").clicked() {
+                        self.engine.copy_style();
+                        ui.close_menu();
+                    }
+                    if ui.button("paste style (alt+P)").clicked() {
+                        self.engine.paste_style();
+                        ui.close_menu();
+                    }
+                });
+                ui.toggle_value(&mut self.engine.style_picker_is_active, "pick style").on_hover_ui(|ui| {
+                    ui.label("click on an object to use its style for drawing");
+                });
+                if ui.button("undo").clicked() {
+                    self.engine.undo();
+                }
+                if ui.button("redo").clicked() {
+                    self.engine.redo();
+                }
+
+                ui.separator();
+
+                ui.toggle_value(&mut self.engine.snapping.grid_is_visible, "grid");
+                ui.add(egui::DragValue::new(&mut self.engine.snapping.grid_spacing.value).range(5.0..=500.0)).on_hover_ui_at_pointer(|ui| {
+                    ui.label("grid spacing");
+                });
+                ui.toggle_value(&mut self.engine.snapping.snap_to_grid, "snap to grid");
+                ui.toggle_value(&mut self.engine.snapping.snap_to_objects, "snap to objects");
             });
 
             ui.separator();
@@ -113,62 +387,109 @@ impl eframe::App for App {
 
                 let mut p = EguiPainter::new(painter, ctx.clone());
 
-                let user_input = if modal_dialog_is_open {
+                let mut user_input = if modal_dialog_is_open {
                     UserInput::Nothing
                 }
                 else {
                     map_user_input(&response, ui)
                 };
+                if self.fg_color_selector.eyedropper_is_active || self.bg_color_selector.eyedropper_is_active {
+                    user_input = self.update_eyedropper(user_input);
+                }
+                if !modal_dialog_is_open {
+                    clipboard_action = clipboard_action.or(self.clipboard_shortcut(ctx));
+                }
+                if let Some(action) = clipboard_action {
+                    // from the menu the mouse is over the toolbar, not the canvas
+                    self.run_clipboard_action(action, response.hover_pos().map(Vector2::from));
+                }
                 let screen_rect = ui.ctx().input(|input| input.screen_rect);
 
-                if let Err(err) = self.engine.update(user_input, self.stroke, screen_rect.width(), screen_rect.height()) {
-                    self.error_window.is_open = true;
-                    self.error_msg = err;
+                if let Err(err) = self.engine.update(user_input, self.stroke, &self.fill, screen_rect.width(), screen_rect.height()) {
+                    self.toasts.error("Could not insert the picture.", &err);
+                }
+                for err in self.engine.take_loading_errors() {
+                    self.toasts.error("Could not load the picture.", &err);
+                }
+                if self.engine.is_loading() {
+                    // keep the loading indicators moving and check for finished pictures
+                    ctx.request_repaint();
+                }
+                else if self.engine.is_watching_files() {
+                    ctx.request_repaint_after(FILE_WATCH_INTERVAL);
+                }
+                // egui keeps the earliest of the requested repaints
+                if let Some(delay) = self.engine.next_animation_frame() {
+                    ctx.request_repaint_after(delay);
+                }
+                if let Some(style) = self.engine.take_picked_style() {
+                    if let Some(stroke) = style.stroke {
+                        self.stroke = stroke;
+                    }
+                    if let Some(fill) = style.fill {
+                        self.fill = fill;
+                    }
                 }
 
                 self.engine.draw(&mut p, self.bg_color);
+
+                if self.fg_color_selector.eyedropper_is_active || self.bg_color_selector.eyedropper_is_active {
+                    ctx.set_cursor_icon(egui::CursorIcon::Crosshair);
+                    if let Some(hover_position) = response.hover_pos() {
+                        // preview of the color that would be picked, next to the cursor
+                        let color = self.engine.color_at(Vector2::from(hover_position), self.bg_color);
+                        let preview = egui::Rect::from_min_size(hover_position + egui::Vec2::splat(12.0), egui::Vec2::splat(24.0));
+                        ui.painter().rect_filled(preview, 2.0, color);
+                        ui.painter().rect_stroke(preview, 2.0, egui::Stroke::new(1.0, color.inverse()));
+                    }
+                }
             });
 
-            self.fg_color_selector.update(ctx, &mut self.stroke.color);
-            self.bg_color_selector.update(ctx, &mut self.bg_color);
-            self.error_window.show(ctx, |ui| {
-                ui.heading("Error");
-                ui.label(&self.error_msg);
+            let fg_result = self.fg_color_selector.update(ctx, &mut self.stroke.color, &mut self.color_palette);
+            let bg_result = self.bg_color_selector.update(ctx, &mut self.bg_color, &mut self.color_palette);
+            if let Err(err) = fg_result.and(bg_result) {
+                self.toasts.error("Could not import or export the palette.", &err);
+            }
+            for err in self.color_palette.take_loading_errors() {
+                self.toasts.error("Could not load a palette.", &err);
+            }
+            self.fill_window.show(ctx, |ui| {
+                ui.heading("Fill of new shapes");
+                properties_panel::fill_ui(ui, "new shape fill", &mut self.fill);
             });
+            self.update_recovery(ctx);
+            if let Err(err) = self.autosave.update(&self.engine) {
+                self.toasts.error("Autosave failed, the drawing can't be restored after a crash.", &err);
+            }
+            // saving is only checked when a frame is drawn
+            ctx.request_repaint_after(Autosave::INTERVAL);
+            self.toasts.show(ctx);
 });
     }
 }
 
 fn map_user_input(response: &egui::Response, ui: &egui::Ui) -> UserInput {
     let is_shift_down = ui.input(|input| input.modifiers.shift);
+    let is_alt_down = ui.input(|input| input.modifiers.alt);
     let is_space_down = ui.input(|input| input.key_down(egui::Key::Space));
     let mouse_wheel_delta = ui.input(|input| input.smooth_scroll_delta.y * 0.001);
 
     if mouse_wheel_delta != 0.0 {
         return UserInput::Zoom { delta: mouse_wheel_delta };
     }
-    if ui.input(|input| input.key_pressed(egui::Key::A) && input.modifiers.command) {
-        return UserInput::SelectAll;
-    }
-    if ui.input(|input| input.key_pressed(egui::Key::Escape)) {
-        return UserInput::DeselectAll;
-    }
-    if ui.input(|input| input.key_pressed(egui::Key::Delete)) {
-        return UserInput::Delete;
-    }
-    if ui.input(|input| input.key_pressed(egui::Key::Enter)) {
-        return UserInput::FinalizeClip;
+    if let Some(user_input) = keyboard_shortcut(ui, is_shift_down) {
+        return user_input;
     }
     if response.dragged_by(egui::PointerButton::Middle) {
         let delta = response.drag_delta();
         return UserInput::Pan {
-            delta: Vector2::new(-1.0 * delta.x, -1.0 * delta.y)
+            delta: Vector2::new(-delta.x, -delta.y)
         };
     }
     if response.dragged_by(egui::PointerButton::Primary) && is_space_down {
         let delta = response.drag_delta();
         return UserInput::Pan {
-            delta: Vector2::new(-1.0 * delta.x, -1.0 * delta.y)
+            delta: Vector2::new(-delta.x, -delta.y)
         };
     }
     if response.clicked_by(egui::PointerButton::Primary) {
@@ -195,7 +516,8 @@ fn map_user_input(response: &egui::Response, ui: &egui::Ui) -> UserInput {
                 position: Vector2::from(position),
                 delta: Vector2::from(response.drag_delta()),
                 button: MouseButton::Left,
-                is_shift_down
+                is_shift_down,
+                is_alt_down,
             };
         }
     }
@@ -205,7 +527,8 @@ fn map_user_input(response: &egui::Response, ui: &egui::Ui) -> UserInput {
                 position: Vector2::from(position),
                 delta: Vector2::from(response.drag_delta()),
                 button: MouseButton::Right,
-                is_shift_down
+                is_shift_down,
+                is_alt_down,
             };
         }
     }
@@ -215,9 +538,113 @@ fn map_user_input(response: &egui::Response, ui: &egui::Ui) -> UserInput {
                 position: Vector2::from(position),
                 delta: Vector2::from(response.drag_delta()),
                 button: MouseButton::None,
-                is_shift_down
+                is_shift_down,
+                is_alt_down,
             };
         }
     }
-    return UserInput::Nothing;
+    UserInput::Nothing
+}
+
+
+// The shortcuts are left to text fields and drag values while they have the keyboard focus.
+fn keyboard_shortcut(ui: &egui::Ui, is_shift_down: bool) -> Option<UserInput> {
+    if ui.ctx().wants_keyboard_input() {
+        return None;
+    }
+    if ui.input(|input| input.key_pressed(egui::Key::A) && input.modifiers.command) {
+        return Some(UserInput::SelectAll);
+    }
+    if ui.input(|input| input.key_pressed(egui::Key::Escape)) {
+        return Some(UserInput::DeselectAll);
+    }
+    if ui.input(|input| input.key_pressed(egui::Key::Delete)) {
+        return Some(UserInput::Delete);
+    }
+    if ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+        return Some(UserInput::FinalizeClip);
+    }
+    if ui.input(|input| input.modifiers.alt) {
+        let align_or_distribute = ui.input(|input| {
+            let alignment = |alignment| Some(UserInput::Align { alignment });
+            let distribution = |axis| Some(UserInput::Distribute { axis });
+            if input.key_pressed(egui::Key::H) && input.modifiers.shift {
+                distribution(Axis::Horizontal)
+            }
+            else if input.key_pressed(egui::Key::V) && input.modifiers.shift {
+                distribution(Axis::Vertical)
+            }
+            else if input.key_pressed(egui::Key::ArrowLeft) {
+                alignment(Alignment::Left)
+            }
+            else if input.key_pressed(egui::Key::ArrowRight) {
+                alignment(Alignment::Right)
+            }
+            else if input.key_pressed(egui::Key::ArrowUp) {
+                alignment(Alignment::Top)
+            }
+            else if input.key_pressed(egui::Key::ArrowDown) {
+                alignment(Alignment::Bottom)
+            }
+            else if input.key_pressed(egui::Key::H) {
+                alignment(Alignment::CenterHorizontal)
+            }
+            else if input.key_pressed(egui::Key::V) {
+                alignment(Alignment::CenterVertical)
+            }
+            else if input.key_pressed(egui::Key::C) {
+                Some(UserInput::CopyStyle)
+            }
+            else if input.key_pressed(egui::Key::P) {
+                Some(UserInput::PasteStyle)
+            }
+            else {
+                None
+            }
+        });
+        if align_or_distribute.is_some() {
+            return align_or_distribute;
+        }
+    }
+    if ui.input(|input| input.key_pressed(egui::Key::Z) && input.modifiers.command) {
+        if is_shift_down {
+            return Some(UserInput::Redo);
+        }
+        return Some(UserInput::Undo);
+    }
+    if ui.input(|input| input.key_pressed(egui::Key::Y) && input.modifiers.command) {
+        return Some(UserInput::Redo);
+    }
+    if ui.input(|input| input.key_pressed(egui::Key::H) && input.modifiers.is_none()) {
+        return Some(UserInput::Flip { axis: Axis::Horizontal });
+    }
+    if ui.input(|input| input.key_pressed(egui::Key::V) && input.modifiers.is_none()) {
+        return Some(UserInput::Flip { axis: Axis::Vertical });
+    }
+    if ui.input(|input| input.key_pressed(egui::Key::R) && !input.modifiers.command && !input.modifiers.alt) {
+        let rotation = if is_shift_down { Rotation::CounterClockwise } else { Rotation::Clockwise };
+        return Some(UserInput::Rotate { rotation });
+    }
+    let nudge_direction = ui.input(|input| {
+        if input.key_pressed(egui::Key::ArrowLeft) {
+            Some(Vector2::new(-1.0, 0.0))
+        }
+        else if input.key_pressed(egui::Key::ArrowRight) {
+            Some(Vector2::new(1.0, 0.0))
+        }
+        else if input.key_pressed(egui::Key::ArrowUp) {
+            Some(Vector2::new(0.0, -1.0))
+        }
+        else if input.key_pressed(egui::Key::ArrowDown) {
+            Some(Vector2::new(0.0, 1.0))
+        }
+        else {
+            None
+        }
+    });
+    if let Some(direction) = nudge_direction {
+        let distance = if is_shift_down { 10.0 } else { 1.0 };
+        return Some(UserInput::Nudge { delta: direction * distance });
+    }
+    None
 }
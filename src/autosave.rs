@@ -0,0 +1,228 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+use crate::engine::*;
+use crate::scene_file::SceneWriter;
+use crate::storage;
+use crate::error::Error;
+
+
+// Periodically saves the scene to a recovery file, so that the drawing survives a crash.
+// Every running instance of the app has its own recovery file and a lock file that it keeps locked until it exits.
+// The operating system releases the lock when the process dies, so a lock file that isn't locked belongs to a session that crashed.
+pub struct Autosave {
+    recovery_path: Option<PathBuf>,
+    lock_path: Option<PathBuf>,
+    lock_file: Option<File>,
+    last_save_time: Instant,
+    // the writer is returned from the thread, so that the next save can be skipped if nothing changed
+    saving: Option<JoinHandle<(SceneWriter, Result<(), Error>)>>,
+    last_saved: Option<SceneWriter>,
+    last_error: Option<String>,
+    // set while the user decides whether to restore the recovery file, so that it's not overwritten
+    pub is_paused: bool,
+}
+
+impl Autosave {
+    pub const INTERVAL: Duration = Duration::from_secs(30);
+
+    // Returns the autosaved scene of a previous session if that session crashed.
+    pub fn start() -> (Self, Option<String>) {
+        let id = std::process::id();
+        let recovery_path = storage::data_file(&format!("recovery-{}.scene", id));
+        let lock_path = storage::data_file(&format!("session-{}.lock", id));
+
+        let recovered_scene = recovery_path.as_deref().and_then(recover_crashed_session);
+        // not being able to lock only means that a crash of this session can't be detected
+        let lock_file = lock_path.as_ref().and_then(|path| {
+            // locked under another name, so that other instances that are starting never see it unlocked
+            let temporary_path = path.with_extension("tmp");
+            let file = File::create(&temporary_path).ok()?;
+            file.lock().ok()?;
+            std::fs::rename(&temporary_path, path).ok()?;
+            Some(file)
+        });
+
+        let autosave = Self {
+            recovery_path,
+            lock_path,
+            lock_file,
+            last_save_time: Instant::now(),
+            saving: None,
+            last_saved: None,
+            last_error: None,
+            is_paused: recovered_scene.is_some(),
+        };
+        (autosave, recovered_scene)
+    }
+
+    // Starts saving in the background when it's time to do so. Returns an error only the first time a save fails.
+    pub fn update<P: ScreenPainter, IconType>(&mut self, engine: &Engine<P, IconType>) -> Result<(), Error> {
+        let mut result = Ok(());
+        if self.saving.as_ref().is_some_and(|saving| saving.is_finished()) {
+            if let Some(Ok((writer, save_result))) = self.saving.take().map(JoinHandle::join) {
+                self.last_saved = Some(writer);
+                match save_result {
+                    Ok(()) => self.last_error = None,
+                    Err(err) => {
+                        // compared by the message, io errors can't be compared
+                        let message = err.to_string();
+                        if self.last_error.as_ref() != Some(&message) {
+                            result = Err(err);
+                        }
+                        self.last_error = Some(message);
+                        // try again next time even if nothing changes
+                        self.last_saved = None;
+                    },
+                }
+            }
+        }
+
+        if self.is_paused || self.saving.is_some() || self.last_save_time.elapsed() < Self::INTERVAL {
+            return result;
+        }
+        self.last_save_time = Instant::now();
+        let Some(recovery_path) = self.recovery_path.clone() else {
+            return result;
+        };
+
+        let mut writer = SceneWriter::new();
+        engine.save_objects(&mut writer);
+        if self.last_saved.as_ref() == Some(&writer) {
+            return result;
+        }
+        self.saving = Some(std::thread::spawn(move || {
+            let save_result = if writer.is_empty() {
+                // there is nothing to restore
+                remove_file_if_exists(&recovery_path)
+            }
+            else {
+                writer.finish().and_then(|text| write_atomically(&recovery_path, &text))
+            };
+            (writer, save_result)
+        }));
+        result
+    }
+
+    pub fn discard_recovery_file(&mut self) {
+        if let Some(path) = &self.recovery_path {
+            let _ = remove_file_if_exists(path);
+        }
+        self.last_saved = None;
+    }
+
+    // Called when the app exits cleanly, there is nothing to recover after that.
+    // Unless the user hasn't decided about the recovered scene yet, then the lock file is left behind unlocked,
+    // so that the next start sees this session as crashed and offers the scene again.
+    pub fn stop(&mut self) {
+        if let Some(saving) = self.saving.take() {
+            let _ = saving.join();
+        }
+        // unlocked first, some systems don't remove files that are in use
+        self.lock_file = None;
+        if self.is_paused {
+            return;
+        }
+        self.discard_recovery_file();
+        if let Some(path) = &self.lock_path {
+            let _ = remove_file_if_exists(path);
+        }
+    }
+}
+
+
+// Takes over the recovery file of the newest session that crashed: it's moved to `recovery_path`,
+// so that it's kept until the user decides what to do with it, also if this session crashes as well.
+// The files of older crashed sessions are left for the next start.
+fn recover_crashed_session(recovery_path: &Path) -> Option<String> {
+    let dir = recovery_path.parent()?;
+    // the modification time and the files of the crashed session, its lock file stays locked until it's taken over
+    let mut newest: Option<(SystemTime, PathBuf, PathBuf, File)> = None;
+    for entry in std::fs::read_dir(dir).ok()?.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // older versions used "session.lock" and "recovery.scene"
+        let Some(suffix) = name.strip_prefix("session").and_then(|rest| rest.strip_suffix(".lock")) else {
+            continue;
+        };
+        let lock_path = entry.path();
+        let Ok(lock_file) = File::options().read(true).write(true).open(&lock_path) else {
+            continue;
+        };
+        // fails for the sessions that are still running, and where locking isn't supported
+        if lock_file.try_lock().is_err() {
+            continue;
+        }
+
+        let crashed_recovery_path = dir.join(format!("recovery{}.scene", suffix));
+        let Ok(modified) = std::fs::metadata(&crashed_recovery_path).and_then(|metadata| metadata.modified()) else {
+            // there is nothing to restore
+            drop(lock_file);
+            let _ = remove_file_if_exists(&lock_path);
+            continue;
+        };
+        if newest.as_ref().is_none_or(|(newest_modified, ..)| modified > *newest_modified) {
+            newest = Some((modified, lock_path, crashed_recovery_path, lock_file));
+        }
+    }
+
+    let (_, lock_path, crashed_recovery_path, lock_file) = newest?;
+    let scene = std::fs::read_to_string(&crashed_recovery_path).ok()?;
+    // if it can't be moved then the crashed session stays as it is and is offered again next time
+    if std::fs::rename(&crashed_recovery_path, recovery_path).is_ok() {
+        drop(lock_file);
+        let _ = remove_file_if_exists(&lock_path);
+    }
+    Some(scene)
+}
+
+
+// Writes to a temporary file first, so that a crash while writing doesn't destroy the previous recovery file.
+fn write_atomically(path: &PathBuf, text: &str) -> Result<(), Error> {
+    let temporary_path = path.with_extension("tmp");
+    std::fs::write(&temporary_path, text).map_err(|err| Error::io(&temporary_path, err))?;
+    std::fs::rename(&temporary_path, path).map_err(|err| Error::io(path, err))
+}
+
+
+fn remove_file_if_exists(path: &PathBuf) -> Result<(), Error> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(Error::io(path, err)),
+        _                                                      => Ok(()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undecided_recovery_survives_exit() {
+        let dir = std::env::temp_dir().join(format!("picipaint-autosave-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        // the only test that touches the data directory
+        std::env::set_var("XDG_DATA_HOME", &dir);
+        let data_dir = storage::data_dir().unwrap();
+        std::fs::write(data_dir.join("recovery-crashed.scene"), "scene").unwrap();
+        std::fs::write(data_dir.join("session-crashed.lock"), "").unwrap();
+
+        // exiting without deciding keeps the scene for the next start
+        let (mut autosave, scene) = Autosave::start();
+        assert_eq!(scene.as_deref(), Some("scene"));
+        assert!(autosave.is_paused);
+        autosave.stop();
+        let (mut autosave, scene) = Autosave::start();
+        assert_eq!(scene.as_deref(), Some("scene"));
+
+        // discarding it doesn't
+        autosave.discard_recovery_file();
+        autosave.is_paused = false;
+        autosave.stop();
+        let (mut autosave, scene) = Autosave::start();
+        assert_eq!(scene, None);
+        autosave.stop();
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,94 @@
+use std::borrow::Cow;
+use std::path::PathBuf;
+use crate::error::Error;
+
+
+// What can be pasted into the drawing.
+pub enum ClipboardContent {
+    Image(image::DynamicImage),
+    // copied files show up as their paths or "file://" URIs in the text of the clipboard
+    Files(Vec<PathBuf>),
+    Nothing,
+}
+
+
+// The system clipboard, opened on first use.
+// It's kept open because on some systems what we copied disappears when it's closed.
+#[derive(Default)]
+pub struct Clipboard {
+    clipboard: Option<arboard::Clipboard>,
+}
+
+impl Clipboard {
+    pub fn paste(&mut self) -> Result<ClipboardContent, Error> {
+        let clipboard = self.open()?;
+        match clipboard.get_image() {
+            Ok(data) => {
+                let image = image::RgbaImage::from_raw(data.width as u32, data.height as u32, data.bytes.into_owned())
+                    .ok_or(Error::Clipboard { source: arboard::Error::ConversionFailure })?;
+                return Ok(ClipboardContent::Image(image::DynamicImage::ImageRgba8(image)));
+            },
+            Err(arboard::Error::ContentNotAvailable) => {},
+            Err(err)                                 => return Err(Error::Clipboard { source: err }),
+        }
+
+        let text = match clipboard.get_text() {
+            Ok(text)                                 => text,
+            Err(arboard::Error::ContentNotAvailable) => return Ok(ClipboardContent::Nothing),
+            Err(err)                                 => return Err(Error::Clipboard { source: err }),
+        };
+        let paths = text.lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty())
+                        .map(path_from_line)
+                        .collect::<Vec<_>>();
+        if !paths.is_empty() && paths.iter().all(|path| path.is_file()) {
+            Ok(ClipboardContent::Files(paths))
+        }
+        else {
+            Ok(ClipboardContent::Nothing)
+        }
+    }
+
+    pub fn copy_image(&mut self, image: &image::RgbaImage) -> Result<(), Error> {
+        let data = arboard::ImageData {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            bytes: Cow::Borrowed(image.as_raw()),
+        };
+        self.open()?.set_image(data).map_err(|err| Error::Clipboard { source: err })
+    }
+
+    pub fn copy_text(&mut self, text: String) -> Result<(), Error> {
+        self.open()?.set_text(text).map_err(|err| Error::Clipboard { source: err })
+    }
+
+    fn open(&mut self) -> Result<&mut arboard::Clipboard, Error> {
+        if self.clipboard.is_none() {
+            self.clipboard = Some(arboard::Clipboard::new().map_err(|err| Error::Clipboard { source: err })?);
+        }
+        Ok(self.clipboard.as_mut().unwrap())
+    }
+}
+
+
+// File managers copy files as "file://" URIs, where special characters like spaces are percent-encoded.
+fn path_from_line(line: &str) -> PathBuf {
+    let Some(uri_path) = line.strip_prefix("file://") else {
+        return PathBuf::from(line);
+    };
+    let bytes = uri_path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3)
+                           .and_then(|hex| std::str::from_utf8(hex).ok())
+                           .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                           .filter(|_| bytes[i] == b'%');
+        match escaped {
+            Some(byte) => { decoded.push(byte); i += 3; },
+            None       => { decoded.push(bytes[i]); i += 1; },
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&decoded).into_owned())
+}
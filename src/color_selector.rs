@@ -1,43 +1,170 @@
 use crate::primitives::*;
 use crate::floating_window::FloatingWindow;
+use crate::palette::*;
+use crate::error::Error;
 use eframe::egui;
+use eframe::egui::ecolor::Hsva;
+use eframe::egui::color_picker::{self, Alpha};
 
 
 pub struct ColorSelector {
     old_color: Option<Color>,
     title: String,
+    // Kept between frames because the hue and saturation can't be recovered from gray colors.
+    hsva: Option<Hsva>,
+    hex_text: String,
     pub window: FloatingWindow,
+    // While this is set the window is hidden and the app picks the color from the canvas,
+    // see `finish_eyedropper`.
+    pub eyedropper_is_active: bool,
 }
 
 impl ColorSelector {
+    const SWATCH_SIZE: f32 = 16.0;
+
     pub fn new(title: &str) -> Self {
         Self {
             title: String::from(title),
             window: FloatingWindow::new(title),
             old_color: None,
+            hsva: None,
+            hex_text: String::new(),
+            eyedropper_is_active: false,
         }
     }
-    
-    pub fn update(&mut self, ctx: &egui::Context, color: &mut Color) {
+
+    pub fn update(&mut self, ctx: &egui::Context, color: &mut Color, palette: &mut ColorPalette) -> Result<(), Error> {
+        let mut result = Ok(());
         self.window.show(ctx, |ui| {
             ui.heading(&self.title);
-            let old_color = self.old_color.get_or_insert(*color);
+            let old_color = *self.old_color.get_or_insert(*color);
             egui::Frame::canvas(ui.style()).show(ui, |ui| {
                 let w = 100.0;
                 let h = 100.0;
                 let (response, painter) = ui.allocate_painter(egui::Vec2::new(w, h), egui::Sense::hover());
                 let origin = response.rect.min;
-                painter.rect_filled(egui::Rect::from_min_size(origin,                                 egui::Vec2::new(w / 2.0, h)), 0.0, *old_color);
-                painter.rect_filled(egui::Rect::from_min_size(origin + egui::Vec2::new(w / 2.0, 0.0), egui::Vec2::new(w / 2.0, h)), 0.0, *color);
+                color_picker::show_color_at(&painter, old_color.into(), egui::Rect::from_min_size(origin,                                 egui::Vec2::new(w / 2.0, h)));
+                color_picker::show_color_at(&painter, (*color).into(),  egui::Rect::from_min_size(origin + egui::Vec2::new(w / 2.0, 0.0), egui::Vec2::new(w / 2.0, h)));
             });
 
+            let mut hsva = self.hsva.filter(|hsva| color_from_hsva(*hsva) == *color).unwrap_or_else(|| hsva_from_color(*color));
+            if color_picker::color_picker_hsva_2d(ui, &mut hsva, Alpha::OnlyBlend) {
+                *color = color_from_hsva(hsva);
+            }
+            self.hsva = Some(hsva);
+
             ui.add(egui::Slider::new(&mut color.red, 0..=255).text("red"));
             ui.add(egui::Slider::new(&mut color.green, 0..=255).text("green"));
             ui.add(egui::Slider::new(&mut color.blue, 0..=255).text("blue"));
+            ui.add(egui::Slider::new(&mut color.alpha, 0..=255).text("alpha"));
+
+            ui.horizontal(|ui| {
+                ui.label("hex");
+                let response = ui.add(egui::TextEdit::singleline(&mut self.hex_text).desired_width(80.0));
+                if response.changed() {
+                    if let Some(parsed) = Color::from_hex(&self.hex_text) {
+                        *color = parsed;
+                    }
+                }
+                // only overwrite the text while the user isn't typing into it
+                if !response.has_focus() {
+                    self.hex_text = color.to_hex();
+                }
+                if ui.button("eyedropper").on_hover_text("pick a color from the canvas").clicked() {
+                    self.eyedropper_is_active = true;
+                }
+            });
+
+            ui.separator();
+            ui.label("recent");
+            ui.horizontal_wrapped(|ui| {
+                for recent in palette.recent.iter() {
+                    if Self::swatch(ui, *recent).clicked() {
+                        *color = *recent;
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let selected_name = palette.selected_palette().name.clone();
+                egui::ComboBox::new((&self.title, "palette"), "").selected_text(selected_name).show_ui(ui, |ui| {
+                    for i in 0..palette.palettes.len() {
+                        ui.selectable_value(&mut palette.selected, i, &palette.palettes[i].name);
+                    }
+                });
+                if ui.small_button("new").clicked() {
+                    palette.add_palette(NamedPalette { name: String::from("Palette"), swatches: Vec::new() });
+                }
+                if ui.small_button("delete").clicked() {
+                    palette.remove_selected_palette_if_confirmed();
+                }
+                if ui.small_button("import").on_hover_text("GIMP palette (.gpl) or a list of hex colors").clicked() {
+                    result = palette.import_from_file_dialog().map(|_| ());
+                }
+                if ui.small_button("export").clicked() {
+                    result = palette.export_selected_with_file_dialog().map(|_| ());
+                }
+            });
+            ui.horizontal_wrapped(|ui| {
+                let mut to_be_removed = None;
+                for (i, swatch) in palette.selected_palette().swatches.iter().enumerate() {
+                    let hint = if swatch.name.is_empty() { swatch.color.to_hex() } else { format!("{} {}", swatch.name, swatch.color.to_hex()) };
+                    let response = Self::swatch(ui, swatch.color).on_hover_text(hint + "\nright click to remove");
+                    if response.clicked() {
+                        *color = swatch.color;
+                    }
+                    if response.secondary_clicked() {
+                        to_be_removed = Some(i);
+                    }
+                }
+                if let Some(i) = to_be_removed {
+                    palette.remove_from_selected(i);
+                }
+                if ui.small_button("+").on_hover_text("add the current color").clicked() {
+                    palette.add_to_selected(*color);
+                }
+            });
         });
 
-        if !self.window.is_open {
-            self.old_color = None;
+        if self.eyedropper_is_active {
+            self.window.is_open = false;
+        }
+        else if !self.window.is_open {
+            if let Some(old_color) = self.old_color.take() {
+                if old_color != *color {
+                    palette.add_recent(*color);
+                }
+            }
+            self.hsva = None;
         }
+
+        result
     }
+
+    // `picked_color` is `None` when picking was cancelled.
+    pub fn finish_eyedropper(&mut self, color: &mut Color, picked_color: Option<Color>) {
+        if let Some(picked_color) = picked_color {
+            *color = picked_color;
+        }
+        self.eyedropper_is_active = false;
+        self.window.is_open = true;
+    }
+
+    fn swatch(ui: &mut egui::Ui, color: Color) -> egui::Response {
+        let (rect, response) = ui.allocate_exact_size(egui::Vec2::splat(Self::SWATCH_SIZE), egui::Sense::click());
+        color_picker::show_color_at(ui.painter(), color.into(), rect);
+        ui.painter().rect_stroke(rect, 0.0, ui.visuals().widgets.noninteractive.bg_stroke);
+        response
+    }
+}
+
+
+fn hsva_from_color(color: Color) -> Hsva {
+    Hsva::from_srgba_unmultiplied([color.red, color.green, color.blue, color.alpha])
+}
+
+
+fn color_from_hsva(hsva: Hsva) -> Color {
+    let [red, green, blue, alpha] = hsva.to_srgba_unmultiplied();
+    Color { red, green, blue, alpha }
 }
@@ -1,3 +1,6 @@
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
 use eframe::egui;
 use crate::primitives::*;
 use crate::engine;
@@ -10,10 +13,21 @@ pub struct EguiPainter {
 
 
 impl engine::ScreenPainter for EguiPainter {
-    type Texture = egui::TextureHandle;
+    type Texture = PictureTexture;
     
     fn draw_line(&mut self, start: Vector2<ScreenSpace>, end: Vector2<ScreenSpace>, stroke: Stroke<ScreenSpace>) {
-        self.painter.line_segment([egui::Pos2::from(start), egui::Pos2::from(end)], egui::Stroke::from(stroke));
+        self.draw_polyline(&[start, end], stroke);
+    }
+
+    fn draw_polyline(&mut self, points: &[Vector2<ScreenSpace>], stroke: Stroke<ScreenSpace>) {
+        if let Some(pattern) = stroke.dash.lengths(stroke.thickness) {
+            for dash in split_into_dashes(points, pattern) {
+                self.draw_solid_polyline(&dash, stroke);
+            }
+        }
+        else {
+            self.draw_solid_polyline(points, stroke);
+        }
     }
     
     fn draw_circle(&mut self, center: Vector2<ScreenSpace>, radius: Number<ScreenSpace>, stroke: Stroke<ScreenSpace>) {
@@ -36,29 +50,406 @@ impl engine::ScreenPainter for EguiPainter {
         }
     }
 
-    fn load_image(&mut self, name: &str, image: &image::DynamicImage) -> Self::Texture {
-        let size = [image.width() as usize, image.height() as usize];
-        let image_buffer = image.to_rgba8();
-        let pixels = image_buffer.as_flat_samples();
-        let color_image = egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice());
-        self.context.load_texture(name, color_image, egui::TextureOptions::default())
+    fn draw_polygon_filled(&mut self, points: &[Vector2<ScreenSpace>], fill: &Fill, frame: Rectangle<ScreenSpace>) {
+        let mut mesh = egui::Mesh::default();
+        if let Fill::Solid(color) = fill {
+            for p in points {
+                mesh.colored_vertex(egui::Pos2::from(*p), egui::Color32::from(*color));
+            }
+            for [a, b, c] in triangulate_polygon(points) {
+                mesh.add_triangle(a as u32, b as u32, c as u32);
+            }
+        }
+        else if *fill != Fill::None {
+            // Colors are interpolated linearly inside a triangle, which is not how gradients work,
+            // so every triangle is split into pieces small enough for the error to be invisible.
+            for [a, b, c] in triangulate_polygon(points) {
+                Self::add_subdivided_triangle(&mut mesh, [points[a], points[b], points[c]], |p| {
+                    fill.color_at(frame.point_to_unit(p)).map_or(egui::Color32::TRANSPARENT, egui::Color32::from)
+                });
+            }
+        }
+        self.painter.add(egui::Shape::mesh(mesh));
+    }
+
+    fn load_image(&mut self, name: &str, image: &Arc<image::DynamicImage>) -> Self::Texture {
+        let max_texture_side = self.context.input(|input| input.max_texture_side) as u32;
+        PictureTexture {
+            name: String::from(name),
+            // room for the border of the tiles
+            tile_size: max_texture_side.saturating_sub(2).clamp(1, PictureTexture::MAXIMUM_TILE_SIZE),
+            levels: RefCell::new(vec![TextureLevel::new(Arc::clone(image))]),
+            downscaling: RefCell::new(None),
+        }
     }
     
-    fn draw_image(&mut self, frame: Rectangle<ScreenSpace>, texture: &Self::Texture) {
-            self.painter.image(texture.id(),
-                               egui::Rect::from(frame),
-                               egui::Rect::from_min_max(egui::Pos2 { x: 0.0, y: 0.0 }, egui::Pos2 { x: 1.0, y: 1.0 }),
+    fn draw_image(&mut self, frame: Rectangle<ScreenSpace>, texture: &Self::Texture, flip: Flip) {
+        let frame = egui::Rect::from(frame);
+        let pixels_per_point = self.context.pixels_per_point();
+        let wanted_level = texture.level_for_size(frame.width() * pixels_per_point, frame.height() * pixels_per_point);
+        let level_index = texture.closest_built_level(wanted_level, &self.context);
+        let mut levels = texture.levels.borrow_mut();
+        // the finer levels are the large ones, only the one in use and the smaller ones are kept on the GPU
+        for level in levels[..level_index].iter_mut() {
+            level.tiles.clear();
+        }
+
+        let level = &mut levels[level_index];
+        let (width, height) = (level.image.width(), level.image.height());
+        if level.tiles.is_empty() {
+            level.tiles = (0..height.div_ceil(texture.tile_size)).flat_map(|row| {
+                (0..width.div_ceil(texture.tile_size)).map(move |column| {
+                    let x = column * texture.tile_size;
+                    let y = row * texture.tile_size;
+                    Tile {
+                        x,
+                        y,
+                        width: texture.tile_size.min(width - x),
+                        height: texture.tile_size.min(height - y),
+                        handle: None,
+                    }
+                })
+            }).collect();
+        }
+
+        let clip_rect = self.painter.clip_rect();
+        for (i, tile) in level.tiles.iter_mut().enumerate() {
+            // the part of the picture covered by the tile, mirrored along with the picture
+            let mut u = (tile.x as f32 / width as f32, (tile.x + tile.width) as f32 / width as f32);
+            let mut v = (tile.y as f32 / height as f32, (tile.y + tile.height) as f32 / height as f32);
+            if flip.horizontal {
+                u = (1.0 - u.1, 1.0 - u.0);
+            }
+            if flip.vertical {
+                v = (1.0 - v.1, 1.0 - v.0);
+            }
+            let tile_rect = egui::Rect::from_min_max(frame.lerp_inside(egui::vec2(u.0, v.0)), frame.lerp_inside(egui::vec2(u.1, v.1)));
+            if !clip_rect.intersects(tile_rect) {
+                continue;
+            }
+
+            // Every tile also gets the pixels next to it, so that linear filtering blends into the neighbouring tiles
+            // instead of leaving seams. Only the tile's own part of the texture is drawn.
+            let left = tile.x.saturating_sub(1);
+            let top = tile.y.saturating_sub(1);
+            let texture_width = (tile.x + tile.width + 1).min(width) - left;
+            let texture_height = (tile.y + tile.height + 1).min(height) - top;
+            // tiles are only uploaded once they are visible
+            let handle = tile.handle.get_or_insert_with(|| {
+                let pixels = level.image.crop_imm(left, top, texture_width, texture_height).to_rgba8();
+                let size = [texture_width as usize, texture_height as usize];
+                let color_image = egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_flat_samples().as_slice());
+                let tile_name = format!("{} (level {}, tile {})", texture.name, level_index, i);
+                self.context.load_texture(tile_name, color_image, egui::TextureOptions::LINEAR)
+            });
+
+            let mut u = ((tile.x - left) as f32 / texture_width as f32, (tile.x - left + tile.width) as f32 / texture_width as f32);
+            let mut v = ((tile.y - top) as f32 / texture_height as f32, (tile.y - top + tile.height) as f32 / texture_height as f32);
+            // mirroring is done by swapping the texture coordinates of the opposite sides
+            if flip.horizontal {
+                u = (u.1, u.0);
+            }
+            if flip.vertical {
+                v = (v.1, v.0);
+            }
+            self.painter.image(handle.id(),
+                               tile_rect,
+                               egui::Rect::from_min_max(egui::Pos2 { x: u.0, y: v.0 }, egui::Pos2 { x: u.1, y: v.1 }),
                                egui::Color32::WHITE);
+        }
     }
 }
 
+
+// A picture as a pyramid of more and more downscaled versions, each split into tiles that fit into a texture.
+// Levels are created when they are first needed, so zooming out doesn't upload the full resolution image.
+pub struct PictureTexture {
+    name: String,
+    tile_size: u32,
+    // level 0 is the original image, every further level has half the width and height of the previous one
+    levels: RefCell<Vec<TextureLevel>>,
+    // the next level while it's being downscaled on a worker thread
+    downscaling: RefCell<Option<Receiver<Arc<image::DynamicImage>>>>,
+}
+
+impl PictureTexture {
+    // also caps the size of the textures on GPUs that would allow larger ones
+    const MAXIMUM_TILE_SIZE: u32 = 4096;
+
+    // The smallest level that still has at least as many pixels as the screen area it's drawn on.
+    fn level_for_size(&self, screen_width: f32, screen_height: f32) -> usize {
+        let levels = self.levels.borrow();
+        let (width, height) = (levels[0].image.width(), levels[0].image.height());
+        let scale = (width as f32 / screen_width).min(height as f32 / screen_height);
+        // NaN for empty frames
+        if scale.is_nan() || scale < 2.0 {
+            return 0;
+        }
+        let max_level = width.max(height).ilog2() as usize;
+        (scale.log2().floor() as usize).min(max_level)
+    }
+
+    // Downscaling large pictures takes too long for the UI thread, so the levels up to `wanted` are made one by one on a worker thread.
+    // Until they are done the closest level that already exists is drawn.
+    fn closest_built_level(&self, wanted: usize, context: &egui::Context) -> usize {
+        let mut levels = self.levels.borrow_mut();
+        let mut downscaling = self.downscaling.borrow_mut();
+        if let Some(receiver) = downscaling.as_ref() {
+            match receiver.try_recv() {
+                Ok(image)                       => levels.push(TextureLevel::new(image)),
+                Err(TryRecvError::Empty)        => return wanted.min(levels.len() - 1),
+                // the thread panicked, it's tried again
+                Err(TryRecvError::Disconnected) => {},
+            }
+            *downscaling = None;
+        }
+
+        if levels.len() <= wanted {
+            let (sender, receiver) = mpsc::channel();
+            let source = Arc::clone(&levels.last().unwrap().image);
+            let context = context.clone();
+            std::thread::spawn(move || {
+                // the picture might have been deleted in the meantime, then nobody is interested in the result
+                if sender.send(Arc::new(TextureLevel::downscale(&source))).is_ok() {
+                    context.request_repaint();
+                }
+            });
+            *downscaling = Some(receiver);
+        }
+        wanted.min(levels.len() - 1)
+    }
+}
+
+
+struct TextureLevel {
+    image: Arc<image::DynamicImage>,
+    // empty if the level isn't used at the moment
+    tiles: Vec<Tile>,
+}
+
+impl TextureLevel {
+    fn new(image: Arc<image::DynamicImage>) -> Self {
+        Self {
+            image,
+            tiles: Vec::new(),
+        }
+    }
+
+    // the image of the next level
+    fn downscale(image: &image::DynamicImage) -> image::DynamicImage {
+        let width = (image.width() / 2).max(1);
+        let height = (image.height() / 2).max(1);
+        image.resize_exact(width, height, image::imageops::FilterType::Triangle)
+    }
+}
+
+
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    handle: Option<egui::TextureHandle>,
+}
+
+
 impl EguiPainter {
+    // maximum length of the sides of the triangles in a gradient mesh, in pixels
+    const GRADIENT_RESOLUTION: f32 = 8.0;
+    const MAXIMUM_TRIANGLE_SUBDIVISIONS: usize = 64;
+    // maximum length of the pieces of round joins and caps in translucent strokes, in pixels
+    const ARC_RESOLUTION: f32 = 2.0;
+
     pub fn new(painter: egui::Painter, context: egui::Context) -> Self {
         Self {
             painter,
             context,
         }
     }
+
+    fn add_subdivided_triangle(mesh: &mut egui::Mesh, [a, b, c]: [Vector2<ScreenSpace>; 3], color_at: impl Fn(Vector2<ScreenSpace>) -> egui::Color32) {
+        let longest_side = [(b - a).length(), (c - b).length(), (a - c).length()].into_iter().fold(0.0, f32::max);
+        let n = ((longest_side / Self::GRADIENT_RESOLUTION).ceil() as usize).clamp(1, Self::MAXIMUM_TRIANGLE_SUBDIVISIONS);
+
+        // vertex (i, j) is at a + (b - a) * i / n + (c - a) * j / n, for i + j <= n
+        let first_index = mesh.vertices.len() as u32;
+        let mut row_start = Vec::with_capacity(n + 1);
+        for i in 0..=n {
+            row_start.push(mesh.vertices.len() as u32 - first_index);
+            for j in 0..=(n - i) {
+                let p = a + (b - a) * (i as f32 / n as f32) + (c - a) * (j as f32 / n as f32);
+                mesh.colored_vertex(egui::Pos2::from(p), color_at(p));
+            }
+        }
+        let index = |i: usize, j: usize| first_index + row_start[i] + j as u32;
+        for i in 0..n {
+            for j in 0..(n - i) {
+                mesh.add_triangle(index(i, j), index(i + 1, j), index(i, j + 1));
+                if i + j + 1 < n {
+                    mesh.add_triangle(index(i + 1, j), index(i + 1, j + 1), index(i, j + 1));
+                }
+            }
+        }
+    }
+
+    // egui can only draw paths with miter joins and butt caps, everything else is drawn here by hand.
+    fn draw_solid_polyline(&mut self, points: &[Vector2<ScreenSpace>], stroke: Stroke<ScreenSpace>) {
+        let mut points = points.iter().map(|p| egui::Pos2::from(*p)).collect::<Vec<_>>();
+        points.dedup();
+        let radius = stroke.thickness.value * 0.5;
+        let color = egui::Color32::from(stroke.color);
+
+        let (Some(first), Some(last)) = (points.first().copied(), points.last().copied()) else {
+            return;
+        };
+        if points.len() == 1 {
+            // zero length dash (or line), only its caps are visible
+            match stroke.cap {
+                LineCap::Butt   => {},
+                LineCap::Round  => { self.painter.circle_filled(first, radius, color); },
+                LineCap::Square => { self.painter.rect_filled(egui::Rect::from_center_size(first, egui::Vec2::splat(stroke.thickness.value)), 0.0, color); },
+            }
+            return;
+        }
+
+        if stroke.cap == LineCap::Square {
+            let n = points.len();
+            let start_direction = (points[0] - points[1]).normalized();
+            let end_direction   = (points[n - 1] - points[n - 2]).normalized();
+            points[0]     += start_direction * radius;
+            points[n - 1] += end_direction * radius;
+        }
+
+        if color.a() < 255 {
+            self.painter.add(egui::Shape::mesh(Self::translucent_stroke_mesh(&points, radius, stroke, color)));
+            return;
+        }
+
+        match stroke.join {
+            LineJoin::Miter => {
+                self.painter.add(egui::Shape::line(points.clone(), egui::Stroke::from(stroke)));
+            },
+            LineJoin::Round => {
+                for p1p2 in points.windows(2) {
+                    self.painter.line_segment([p1p2[0], p1p2[1]], egui::Stroke::from(stroke));
+                }
+                for p in points[1..points.len() - 1].iter() {
+                    self.painter.circle_filled(*p, radius, color);
+                }
+            },
+            LineJoin::Bevel => {
+                for p1p2 in points.windows(2) {
+                    self.painter.line_segment([p1p2[0], p1p2[1]], egui::Stroke::from(stroke));
+                }
+                for p in points.windows(3) {
+                    // the quad between the ends of the two segments, its outer side is the bevel
+                    let n1 = (p[1] - p[0]).normalized().rot90() * radius;
+                    let n2 = (p[2] - p[1]).normalized().rot90() * radius;
+                    let quad = vec![p[1] + n1, p[1] + n2, p[1] - n1, p[1] - n2];
+                    self.painter.add(egui::Shape::convex_polygon(quad, color, egui::Stroke::NONE));
+                }
+            },
+        }
+
+        if stroke.cap == LineCap::Round {
+            self.painter.circle_filled(first, radius, color);
+            self.painter.circle_filled(last, radius, color);
+        }
+    }
+
+    // The shapes used for opaque strokes overlap at the joins and caps, which makes translucent strokes darker there.
+    // This builds the stroke out of triangles that don't overlap instead, but unlike the shapes they aren't anti-aliased.
+    // Square caps are expected to be added to `points` already.
+    fn translucent_stroke_mesh(points: &[egui::Pos2], radius: f32, stroke: Stroke<ScreenSpace>, color: egui::Color32) -> egui::Mesh {
+        let mut mesh = egui::Mesh::default();
+        let directions = points.windows(2).map(|p| (p[1] - p[0]).normalized()).collect::<Vec<_>>();
+        let lengths = points.windows(2).map(|p| (p[1] - p[0]).length()).collect::<Vec<_>>();
+        let normal = |direction: egui::Vec2| egui::vec2(-direction.y, direction.x) * radius;
+
+        // both sides of each segment at its start and its end, the inner sides are moved to where they meet at the joins
+        let mut starts = directions.iter().enumerate().map(|(i, d)| [points[i] + normal(*d), points[i] - normal(*d)]).collect::<Vec<_>>();
+        let mut ends = directions.iter().enumerate().map(|(i, d)| [points[i + 1] + normal(*d), points[i + 1] - normal(*d)]).collect::<Vec<_>>();
+        let mut joins = Vec::new();
+        for i in 0..directions.len().saturating_sub(1) {
+            let p = points[i + 1];
+            let (n1, n2) = (normal(directions[i]), normal(directions[i + 1]));
+            // the side the path turns towards is the inner one
+            let (inner, sign) = if n1.dot(directions[i + 1]) > 0.0 { (0, 1.0) } else { (1, -1.0) };
+            let outer1 = p - n1 * sign;
+            let outer2 = p - n2 * sign;
+            if (outer2 - outer1).length() < 1e-3 {
+                continue;
+            }
+
+            // cosine of half the angle between the normals, the sides are `radius / cos_half` away from `p` where they meet
+            let bisector = (n1 + n2).normalized();
+            let cos_half = (n1 / radius).dot(bisector);
+            let inset = radius * (1.0 - cos_half * cos_half).max(0.0).sqrt() / cos_half;
+            // Segments that are too short for the inner sides to meet on them overlap a bit, that's rare enough to accept.
+            let center = if cos_half > 1e-3 && inset <= lengths[i] * 0.5 && inset <= lengths[i + 1] * 0.5 {
+                let meeting_point = p + bisector * (sign * radius / cos_half);
+                ends[i][inner] = meeting_point;
+                starts[i + 1][inner] = meeting_point;
+                meeting_point
+            }
+            else {
+                p
+            };
+
+            let outline = match stroke.join {
+                LineJoin::Round => Self::arc(p, outer1 - p, (-n1 * sign).angle(), wrapped_angle((-n2 * sign).angle() - (-n1 * sign).angle())),
+                LineJoin::Miter if cos_half > 1.0 / LineJoin::MITER_LIMIT => vec![outer1, p - bisector * (sign * radius / cos_half), outer2],
+                _ => vec![outer1, outer2],
+            };
+            joins.push((center, outline));
+        }
+
+        for (start, end) in starts.iter().zip(ends.iter()) {
+            add_triangle(&mut mesh, [start[0], start[1], end[1]], color);
+            add_triangle(&mut mesh, [start[0], end[1], end[0]], color);
+        }
+        for (center, outline) in joins {
+            for pair in outline.windows(2) {
+                add_triangle(&mut mesh, [center, pair[0], pair[1]], color);
+            }
+        }
+        if stroke.cap == LineCap::Round {
+            // half circles that go around the ends, from one side of the stroke to the other
+            let caps = [(points[0], -directions[0]), (points[points.len() - 1], directions[directions.len() - 1])];
+            for (center, outwards) in caps {
+                let side = egui::vec2(-outwards.y, outwards.x) * radius;
+                let outline = Self::arc(center, side, side.angle(), -std::f32::consts::PI);
+                for pair in outline.windows(2) {
+                    add_triangle(&mut mesh, [center, pair[0], pair[1]], color);
+                }
+            }
+        }
+        mesh
+    }
+
+    // Points on the circle around `center` through `center + start`, from `start_angle` to `start_angle + angle`.
+    fn arc(center: egui::Pos2, start: egui::Vec2, start_angle: f32, angle: f32) -> Vec<egui::Pos2> {
+        let radius = start.length();
+        let steps = ((angle.abs() * radius / Self::ARC_RESOLUTION).ceil() as usize).clamp(1, 64);
+        (0..=steps).map(|i| center + egui::Vec2::angled(start_angle + angle * i as f32 / steps as f32) * radius).collect()
+    }
+}
+
+
+fn add_triangle(mesh: &mut egui::Mesh, [a, b, c]: [egui::Pos2; 3], color: egui::Color32) {
+    let first_index = mesh.vertices.len() as u32;
+    for p in [a, b, c] {
+        mesh.colored_vertex(p, color);
+    }
+    mesh.add_triangle(first_index, first_index + 1, first_index + 2);
+}
+
+
+// into -PI..=PI, i.e. the shorter way around
+fn wrapped_angle(angle: f32) -> f32 {
+    let tau = std::f32::consts::TAU;
+    let wrapped = angle.rem_euclid(tau);
+    if wrapped > std::f32::consts::PI { wrapped - tau } else { wrapped }
 }
 
 
@@ -88,7 +479,7 @@ impl<T: Tag> From<egui::Vec2> for Vector2<T> {
 
 impl From<Color> for egui::Color32 {
     fn from(other: Color) -> egui::Color32 {
-        egui::Color32::from_rgba_premultiplied(other.red, other.green, other.blue, other.alpha)
+        egui::Color32::from_rgba_unmultiplied(other.red, other.green, other.blue, other.alpha)
     }
 }
 
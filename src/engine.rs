@@ -1,27 +1,54 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use crate::primitives::*;
+use crate::spatial_index::SpatialIndex;
+use crate::snapping::*;
+use crate::scene_file::SceneWriter;
+use crate::error::Error;
+
+
+// How far the mouse can be from the stroke of an object and still count as being over it.
+pub const HIT_TOLERANCE: Number<ScreenSpace> = Number::<ScreenSpace>::new(5.0);
+pub const MAXIMUM_STROKE_THICKNESS: Number<WorldSpace> = Number::<WorldSpace>::new(10.0);
+// how often linked files are checked for changes
+pub const FILE_WATCH_INTERVAL: Duration = Duration::from_secs(1);
 
 
 pub trait ScreenPainter {
     type Texture;
     fn draw_line(&mut self, start: Vector2<ScreenSpace>, end: Vector2<ScreenSpace>, stroke: Stroke<ScreenSpace>);
+    fn draw_polyline(&mut self, points: &[Vector2<ScreenSpace>], stroke: Stroke<ScreenSpace>);
     fn draw_circle(&mut self, center: Vector2<ScreenSpace>, radius: Number<ScreenSpace>, stroke: Stroke<ScreenSpace>);
     fn draw_rectangle(&mut self, rectangle: Rectangle<ScreenSpace>, stroke: Stroke<ScreenSpace>);
     fn draw_rectangle_filled(&mut self, rectangle: Rectangle<ScreenSpace>, color: Color, stroke: Option<Stroke<ScreenSpace>>);
-    fn load_image(&mut self, name: &str, image: &image::DynamicImage) -> Self::Texture;
-    fn draw_image(&mut self, frame: Rectangle<ScreenSpace>, texture: &Self::Texture);
+    // `frame` is the bounding rectangle of the filled object, gradients are relative to it.
+    fn draw_polygon_filled(&mut self, points: &[Vector2<ScreenSpace>], fill: &Fill, frame: Rectangle<ScreenSpace>);
+    // The texture may keep a reference to the image, e.g. to create downscaled versions of it later.
+    fn load_image(&mut self, name: &str, image: &Arc<image::DynamicImage>) -> Self::Texture;
+    fn draw_image(&mut self, frame: Rectangle<ScreenSpace>, texture: &Self::Texture, flip: Flip);
 }
 
 
-pub struct WorldPainter<'a, P: ScreenPainter> {
+// Painters used for exporting the drawing, e.g. to the clipboard. Their textures are the images themselves.
+pub type ExportPainter = dyn ScreenPainter<Texture = Arc<image::DynamicImage>>;
+
+
+pub struct WorldPainter<'a, P: ScreenPainter + ?Sized> {
     screen_painter: &'a mut P,
 }
 
-impl<'a, P: ScreenPainter> WorldPainter<'a, P> {
+impl<'a, P: ScreenPainter + ?Sized> WorldPainter<'a, P> {
     pub fn draw_line(&mut self, start: Vector2<WorldSpace>, end: Vector2<WorldSpace>, stroke: Stroke<WorldSpace>, camera: &Camera) {
         let s = camera.point_to_screen_coordinates(start);
         let e = camera.point_to_screen_coordinates(end);
         self.screen_painter.draw_line(s, e, camera.stroke_to_screen_coordinates(stroke));
     }
+
+    pub fn draw_polyline(&mut self, points: &[Vector2<WorldSpace>], stroke: Stroke<WorldSpace>, camera: &Camera) {
+        let screen_points = points.iter().map(|p| camera.point_to_screen_coordinates(*p)).collect::<Vec<_>>();
+        self.screen_painter.draw_polyline(&screen_points, camera.stroke_to_screen_coordinates(stroke));
+    }
     
     // pub fn draw_circle(&mut self, center: Vector2, radius: f32, stroke: Stroke, camera: &Camera) {
     //     let c = camera.convert_to_screen_coordinates(center);
@@ -45,21 +72,52 @@ impl<'a, P: ScreenPainter> WorldPainter<'a, P> {
     //     self.screen_painter.draw_rectangle_filled(rect, color, stroke.map(|s| s.with_scaled_thickness(camera.zoom)));
     // }
 
-    pub fn load_image(&mut self, name: &str, image: &image::DynamicImage) -> P::Texture {
+    pub fn draw_polygon_filled(&mut self, points: &[Vector2<WorldSpace>], fill: &Fill, frame: Rectangle<WorldSpace>, camera: &Camera) {
+        let screen_points = points.iter().map(|p| camera.point_to_screen_coordinates(*p)).collect::<Vec<_>>();
+        let screen_frame = Rectangle {
+            p1: camera.point_to_screen_coordinates(frame.p1),
+            p2: camera.point_to_screen_coordinates(frame.p2),
+        };
+        self.screen_painter.draw_polygon_filled(&screen_points, fill, screen_frame);
+    }
+
+    pub fn load_image(&mut self, name: &str, image: &Arc<image::DynamicImage>) -> P::Texture {
         self.screen_painter.load_image(name, image)
     }
     
-    pub fn draw_image(&mut self, frame: Rectangle<WorldSpace>, texture: &P::Texture, camera: &Camera) {
+    pub fn draw_image(&mut self, frame: Rectangle<WorldSpace>, texture: &P::Texture, flip: Flip, camera: &Camera) {
         let rect = Rectangle {
             p1: camera.point_to_screen_coordinates(frame.p1),
             p2: camera.point_to_screen_coordinates(frame.p2),
         };
-        self.screen_painter.draw_image(rect, texture);
+        self.screen_painter.draw_image(rect, texture, flip);
     }
 }
 
 
-#[derive(PartialEq, Debug)]
+// The file an object was loaded from.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FileLink {
+    pub path: PathBuf,
+    // Linked objects are read from the file whenever the drawing is opened and reloaded when the file changes.
+    // Embedded objects keep their own copy in the drawing.
+    pub is_linked: bool,
+    // the file couldn't be read and there is nothing to show, only the path is left
+    pub is_missing: bool,
+}
+
+
+// The state of an animated object.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Playback {
+    // a paused animation stays on `frame`, also when the drawing is opened again
+    pub is_playing: bool,
+    pub frame: usize,
+    pub frame_count: usize,
+}
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum MouseButton {
     None,
     Left,
@@ -67,7 +125,41 @@ pub enum MouseButton {
 }
 
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Alignment {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    CenterHorizontal,
+    CenterVertical,
+}
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rotation {
+    Clockwise,
+    CounterClockwise,
+}
+
+impl Rotation {
+    pub fn reversed(self) -> Self {
+        match self {
+            Self::Clockwise        => Self::CounterClockwise,
+            Self::CounterClockwise => Self::Clockwise,
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum UserInput {
     Nothing,
     MouseClick {
@@ -80,6 +172,7 @@ pub enum UserInput {
         delta: Vector2<ScreenSpace>,
         button: MouseButton,
         is_shift_down: bool,
+        is_alt_down: bool,
     },
     SelectAll,
     DeselectAll,
@@ -91,6 +184,25 @@ pub enum UserInput {
     },
     Delete,
     FinalizeClip,
+    Align {
+        alignment: Alignment,
+    },
+    Distribute {
+        axis: Axis,
+    },
+    Nudge {
+        delta: Vector2<ScreenSpace>,
+    },
+    Flip {
+        axis: Axis,
+    },
+    Rotate {
+        rotation: Rotation,
+    },
+    Undo,
+    Redo,
+    CopyStyle,
+    PasteStyle,
 }
 
 impl UserInput {
@@ -102,10 +214,11 @@ impl UserInput {
         }
     }
 
-    pub fn mouse_delta(&self) -> Option<Vector2<ScreenSpace>> {
+    pub fn with_mouse_position(self, new_position: Vector2<ScreenSpace>) -> Self {
         match self {
-            Self::MouseMove { delta, .. } => Some(*delta),
-            _                             => None,
+            Self::MouseClick { button, is_shift_down, .. }                    => Self::MouseClick { position: new_position, button, is_shift_down },
+            Self::MouseMove { delta, button, is_shift_down, is_alt_down, .. } => Self::MouseMove { position: new_position, delta, button, is_shift_down, is_alt_down },
+            other                                                             => other,
         }
     }
 
@@ -131,19 +244,126 @@ pub trait PaintObject<P: ScreenPainter> {
     fn base_mut(&mut self) -> &mut PaintObjectCommon;
     fn update(&mut self, input: &UserInput, camera: &Camera);
     fn draw<'a>(&self, painter: &mut WorldPainter<'a, P>, camera: &Camera);
-    fn is_under_mouse(&self) -> bool;
+    // Like `draw`, but without anything that only makes sense on the screen, e.g. loading indicators.
+    fn draw_for_export<'a>(&self, painter: &mut WorldPainter<'a, ExportPainter>, camera: &Camera);
+    fn is_under_mouse(&self, camera: &Camera) -> bool;
     fn get_bounding_rect(&self) -> Rectangle<WorldSpace>;
+    // The area the object draws on, i.e. the bounding rectangle and the parts of the stroke that reach outside of it.
+    fn visual_rect(&self) -> Rectangle<WorldSpace> {
+        let rect = self.get_bounding_rect();
+        let rect = Rectangle::from_points_well_ordered(rect.p1, rect.p2);
+        match self.stroke() {
+            Some(stroke) => rect.expanded_by(stroke.reach()),
+            None         => rect,
+        }
+    }
     fn shift_with(&mut self, p: Vector2<WorldSpace>);
+    // Maps the current bounding rectangle onto `new_size`.
+    // If `new_size` is not well ordered along an axis then the object is mirrored along that axis.
     fn resize_to(&mut self, new_size: Rectangle<WorldSpace>);
     fn clip_to(&mut self, new_size: Rectangle<WorldSpace>);
+    // Writes one line to the scene file, it starts with the kind of the object, see `scene_file::load_objects`.
+    fn save(&self, writer: &mut SceneWriter);
+
+    // `None` for objects that don't have an outline, e.g. pictures.
+    fn stroke(&self) -> Option<Stroke<WorldSpace>> {
+        None
+    }
+
+    fn set_stroke(&mut self, _stroke: Stroke<WorldSpace>) {}
+
+    // `None` for objects that can't be filled, `Some(Fill::None)` for unfilled objects that could be.
+    fn fill(&self) -> Option<Fill> {
+        None
+    }
+
+    fn set_fill(&mut self, _fill: Fill) {}
+
+    // The color this object has at `point`, `None` where it doesn't cover the point.
+    fn color_at(&self, _point: Vector2<WorldSpace>) -> Option<Color> {
+        None
+    }
+
+    // Objects that can't be clipped are left out when clipping the selection, `clip_to` is never called for them.
+    fn can_be_clipped(&self) -> bool {
+        true
+    }
+
+    // Objects can load their content in the background, e.g. pictures that are being decoded.
+    fn is_loading(&self) -> bool {
+        false
+    }
+
+    // Called every frame, returns `Some` once loading has finished. The engine removes the object if it failed and has nothing to show.
+    fn poll_loading(&mut self) -> Option<Result<(), Error>> {
+        None
+    }
+
+    // `None` for objects that can't be adjusted, e.g. shapes that have a stroke and fill instead.
+    fn adjustments(&self) -> Option<ImageAdjustments> {
+        None
+    }
+
+    fn set_adjustments(&mut self, _adjustments: ImageAdjustments) {}
+
+    // Turns the object by 90 degrees around the center of its bounding rectangle.
+    // Returns false for objects that can't be rotated, e.g. shapes.
+    fn rotate_quarter_turn(&mut self, _rotation: Rotation) -> bool {
+        false
+    }
+
+    // Called every frame, moves animations to the frame that is due. Returns how long until the next one is due,
+    // `None` for objects that aren't playing an animation.
+    fn animate(&mut self) -> Option<Duration> {
+        None
+    }
+
+    // `None` for objects that aren't animated.
+    fn playback(&self) -> Option<Playback> {
+        None
+    }
+
+    // `frame_count` is ignored.
+    fn set_playback(&mut self, _playback: Playback) {}
+
+    // `None` for objects that don't come from a file.
+    fn file_link(&self) -> Option<FileLink> {
+        None
+    }
+
+    // Changing the path loads the object from the new file, `is_missing` is ignored.
+    fn set_file_link(&mut self, _link: FileLink) {}
+
+    // Called every frame, starts reloading the object when its linked file changed. Returns whether there is a file to watch.
+    fn watch_file(&mut self) -> bool {
+        false
+    }
+
+    // Whether resizing keeps the aspect ratio without holding shift.
+    fn keeps_aspect_ratio(&self) -> bool {
+        false
+    }
+
+    // Points that other objects snap to when they are moved close to them.
+    fn snap_points(&self) -> Vec<Vector2<WorldSpace>> {
+        let rect = self.get_bounding_rect();
+        let mut points = rect.vertices().to_vec();
+        points.push(rect.center());
+        points
+    }
 }
 
 
 pub trait Tool<P: ScreenPainter, IconType> {
-    fn update(&mut self, input: &UserInput, stroke: Stroke<WorldSpace>, camera: &Camera) -> Result<Option<Box<dyn PaintObject<P>>>, String>;
+    fn update(&mut self, input: &UserInput, stroke: Stroke<WorldSpace>, fill: &Fill, camera: &Camera) -> Result<Option<Box<dyn PaintObject<P>>>, Error>;
     fn draw<'a>(&self, painter: &mut WorldPainter<'a, P>, background_color: Color, camera: &Camera);
     fn display_name(&self) -> &str;
     fn icon(&self) -> IconType;
+
+    // Whether the mouse position should be snapped before it is passed to `update`.
+    fn uses_snapping(&self) -> bool {
+        true
+    }
 }
 
 
@@ -167,8 +387,59 @@ impl<'a, P: ScreenPainter, IconType> Iterator for ToolIterator<'a, P, IconType>
 }
 
 
+enum ResizeTarget {
+    // the selected objects that are not being clipped, scaled together relative to their combined bounding box
+    Selection(Vec<usize>),
+    ClipRectangle(usize),
+}
+
+struct ResizeGesture {
+    handle: RectangleHandle,
+    target: ResizeTarget,
+    start_rect: Rectangle<WorldSpace>,
+    // The rectangle after the last step. It might be flipped compared to the bounding rectangle of the object,
+    // because objects are free to normalize their bounding rectangle after being mirrored.
+    current_rect: Rectangle<WorldSpace>,
+    // mouse movement since the start of the gesture
+    offset: Vector2<WorldSpace>,
+}
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum GradientHandle {
+    Start,
+    End,
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Style {
+    pub stroke: Option<Stroke<WorldSpace>>,
+    pub fill: Option<Fill>,
+}
+
+
+// Operations that can be undone. Objects are referred to by their index in `Engine::objects`,
+// so the history is cleared whenever objects are removed.
+enum Command {
+    Flip {
+        indices: Vec<usize>,
+        axis: Axis,
+        // the box at the time of the flip
+        rect: Rectangle<WorldSpace>,
+        // where each object would be after the last flip, undo or redo if it hadn't been moved, moves aren't undo steps
+        positions: Vec<Rectangle<WorldSpace>>,
+    },
+    Rotate {
+        indices: Vec<usize>,
+        rotation: Rotation,
+    },
+}
+
+
 pub struct Engine<P: ScreenPainter, IconType> {
     objects: Vec<Box<dyn PaintObject<P>>>,
+    spatial_index: SpatialIndex,
     tools: Vec<Box<dyn Tool<P, IconType>>>,
     to_be_deleted: Vec<usize>,
     selected_tool_index: Option<usize>,
@@ -176,16 +447,39 @@ pub struct Engine<P: ScreenPainter, IconType> {
     view_height: f32,
     camera: Camera,
     objects_are_dragged: bool,
-    object_is_resized_by_vertex: Option<RectangleVertex>,
+    resize: Option<ResizeGesture>,
+    // index of the object whose gradient is being edited on the canvas
+    gradient_drag: Option<(usize, GradientHandle)>,
+    pub snapping: Snapping,
+    // the part of the mouse movement that was swallowed by snapping
+    snap_residual: Vector2<WorldSpace>,
+    snap_indicators: Vec<SnapIndicator>,
+    undo_stack: Vec<Command>,
+    redo_stack: Vec<Command>,
+    copied_style: Option<Style>,
+    // When set, the next left click doesn't select anything, but picks the style of the clicked object.
+    pub style_picker_is_active: bool,
+    picked_style: Option<Style>,
+    // errors of objects that failed to load in the background, until the app takes them
+    loading_errors: Vec<Error>,
+    is_watching_files: bool,
+    // the time until the next frame of an animation is due
+    next_animation_frame: Option<Duration>,
 }
 
 impl<P: ScreenPainter, IconType> Engine<P, IconType> {
-    const MINIMUM_OBJECT_SIZE: Number<WorldSpace> = Number::<WorldSpace>::new(20.0);
+    const MINIMUM_OBJECT_SIZE: Number<ScreenSpace> = Number::<ScreenSpace>::new(1.0);
     const SELECTION_MARKER_SIZE: Number<ScreenSpace> = Number::<ScreenSpace>::new(5.0);
+    const GRADIENT_HANDLE_SIZE: Number<ScreenSpace> = Number::<ScreenSpace>::new(7.0);
+    const SPATIAL_INDEX_CELL_SIZE: f32 = 256.0;
+    const SNAP_TOLERANCE: Number<ScreenSpace> = Number::<ScreenSpace>::new(8.0);
+    const SNAP_INDICATOR_COLOR: Color = Color { red: 255, green: 0, blue: 255, alpha: 255 };
+    const MINIMUM_GRID_SPACING_ON_SCREEN: Number<ScreenSpace> = Number::<ScreenSpace>::new(6.0);
 
     pub fn new(tools: Vec<Box<dyn Tool<P, IconType>>>) -> Self {
         Self {
             objects: Vec::new(),
+            spatial_index: SpatialIndex::new(Self::SPATIAL_INDEX_CELL_SIZE),
             tools,
             to_be_deleted: Vec::new(),
             selected_tool_index: None,
@@ -193,26 +487,444 @@ impl<P: ScreenPainter, IconType> Engine<P, IconType> {
             view_height: 0.0,
             camera: Camera::default(),
             objects_are_dragged: false,
-            object_is_resized_by_vertex: None,
+            resize: None,
+            gradient_drag: None,
+            snapping: Snapping::default(),
+            snap_residual: Vector2::zero(),
+            snap_indicators: Vec::new(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            copied_style: None,
+            style_picker_is_active: false,
+            picked_style: None,
+            loading_errors: Vec::new(),
+            is_watching_files: false,
+            next_animation_frame: None,
         }
     }
 
     pub fn add_object(&mut self, object: impl PaintObject<P> + 'static) {
+        self.spatial_index.push(object.visual_rect());
         self.objects.push(Box::new(object));
     }
 
+    pub fn save_objects(&self, writer: &mut SceneWriter) {
+        for object in self.objects.iter() {
+            object.save(writer);
+        }
+    }
+
+    // Replaces the whole scene, e.g. when restoring it from a file. The history refers to the old objects, so it's cleared.
+    pub fn replace_objects(&mut self, objects: Vec<Box<dyn PaintObject<P>>>) {
+        self.spatial_index = SpatialIndex::new(Self::SPATIAL_INDEX_CELL_SIZE);
+        for object in objects.iter() {
+            self.spatial_index.push(object.visual_rect());
+        }
+        self.objects = objects;
+        self.to_be_deleted.clear();
+        self.objects_are_dragged = false;
+        self.resize = None;
+        self.gradient_drag = None;
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
     pub fn start_clipping(&mut self) {
         for object in self.objects.iter_mut() {
-            if object.base().is_selected {
+            if object.base().is_selected && object.can_be_clipped() {
                 object.base_mut().clip_rectangle = Some(object.get_bounding_rect());
             }
         }
     }
     
-    pub fn update(&mut self, input: UserInput, stroke: Stroke<WorldSpace>, view_width: f32, view_height: f32) -> Result<(), String> {
+    // Aligns the selected objects to the edge or center of the bounding box of the whole selection.
+    pub fn align_selected_objects(&mut self, alignment: Alignment) {
+        let rects = self.selected_bounding_rects();
+        let Some(selection_rect) = rects.iter().map(|(_, rect)| *rect).reduce(|a, b| a.union(&b)) else {
+            return;
+        };
+
+        for (i, rect) in rects {
+            let delta = match alignment {
+                Alignment::Left             => Vector2::new(selection_rect.p1.x - rect.p1.x, 0.0),
+                Alignment::Right            => Vector2::new(selection_rect.p2.x - rect.p2.x, 0.0),
+                Alignment::Top              => Vector2::new(0.0, selection_rect.p1.y - rect.p1.y),
+                Alignment::Bottom           => Vector2::new(0.0, selection_rect.p2.y - rect.p2.y),
+                Alignment::CenterHorizontal => Vector2::new(selection_rect.center().x - rect.center().x, 0.0),
+                Alignment::CenterVertical   => Vector2::new(0.0, selection_rect.center().y - rect.center().y),
+            };
+            self.shift_object(i, delta);
+        }
+    }
+
+    // Moves the selected objects so that the gaps between them are equal.
+    // The first and the last object stay where they are.
+    pub fn distribute_selected_objects(&mut self, axis: Axis) {
+        let mut rects = self.selected_bounding_rects();
+        if rects.len() < 3 {
+            return;
+        }
+
+        let start = |rect: &Rectangle<WorldSpace>| match axis {
+            Axis::Horizontal => rect.p1.x,
+            Axis::Vertical   => rect.p1.y,
+        };
+        let size = |rect: &Rectangle<WorldSpace>| match axis {
+            Axis::Horizontal => rect.width().value,
+            Axis::Vertical   => rect.height().value,
+        };
+
+        rects.sort_by(|(_, a), (_, b)| start(a).total_cmp(&start(b)));
+        let span_start = start(&rects[0].1);
+        // the end of the last object, not the furthest end, a long object in the middle mustn't move the last one
+        let span_end   = rects.last().map_or(span_start, |(_, rect)| start(rect) + size(rect));
+        let total_size = rects.iter().map(|(_, rect)| size(rect)).sum::<f32>();
+        let gap = (span_end - span_start - total_size) / (rects.len() - 1) as f32;
+
+        let mut position = span_start;
+        for (i, rect) in rects {
+            let offset = position - start(&rect);
+            let delta = match axis {
+                Axis::Horizontal => Vector2::new(offset, 0.0),
+                Axis::Vertical   => Vector2::new(0.0, offset),
+            };
+            self.shift_object(i, delta);
+            position += size(&rect) + gap;
+        }
+    }
+
+    // copies the style of the topmost selected object
+    pub fn copy_style(&mut self) {
+        if let Some(object) = self.objects.iter().rev().find(|object| object.base().is_selected) {
+            self.copied_style = Some(Self::style_of(object.as_ref()));
+        }
+    }
+
+    // applies the copied style to all the selected objects, as far as they support it
+    pub fn paste_style(&mut self) {
+        let Some(style) = self.copied_style.as_ref() else {
+            return;
+        };
+        for (i, object) in self.objects.iter_mut().enumerate().filter(|(_, object)| object.base().is_selected) {
+            if let (Some(stroke), Some(_)) = (style.stroke, object.stroke()) {
+                object.set_stroke(stroke);
+                // a thicker stroke reaches further
+                self.spatial_index.update(i, object.visual_rect());
+            }
+            if let (Some(fill), Some(_)) = (&style.fill, object.fill()) {
+                object.set_fill(fill.clone());
+            }
+        }
+    }
+
+    // the style picked with the style picker since the last call
+    pub fn take_picked_style(&mut self) -> Option<Style> {
+        self.picked_style.take()
+    }
+
+    fn style_of(object: &dyn PaintObject<P>) -> Style {
+        Style {
+            stroke: object.stroke(),
+            fill: object.fill(),
+        }
+    }
+
+    fn pick_style(&mut self, input: &UserInput, position: Vector2<ScreenSpace>) {
+        let p = self.camera.point_to_world_coordinates(position);
+        let margin = self.camera.size_to_world_coordinates(HIT_TOLERANCE);
+        let candidates = self.spatial_index.query_rect(Rectangle::from_center_and_side_length(p, margin * 2.0));
+        // going in reverse order, so that the topmost object wins
+        for i in candidates.into_iter().rev() {
+            let object = &mut self.objects[i];
+            object.update(input, &self.camera);
+            if object.is_under_mouse(&self.camera) {
+                self.picked_style = Some(Self::style_of(object.as_ref()));
+                break;
+            }
+        }
+        self.style_picker_is_active = false;
+    }
+
+    // The color that is drawn at `position`, i.e. the objects there blended on top of each other and the background.
+    pub fn color_at(&self, position: Vector2<ScreenSpace>, bg_color: Color) -> Color {
+        let p = self.camera.point_to_world_coordinates(position);
+        let candidates = self.spatial_index.query_rect(Rectangle { p1: p, p2: p });
+        // the objects are drawn in increasing index order, so the last one is on top
+        candidates.into_iter()
+            .filter_map(|i| self.objects[i].color_at(p))
+            .fold(bg_color, |color, object_color| object_color.over(color))
+    }
+
+    // The stroke of the first selected object that has one,
+    // and whether all the other selected objects have the same stroke.
+    pub fn selection_stroke(&self) -> Option<(Stroke<WorldSpace>, bool)> {
+        let mut strokes = self.objects.iter().filter(|object| object.base().is_selected).filter_map(|object| object.stroke());
+        let first = strokes.next()?;
+        let is_common = strokes.all(|stroke| stroke == first);
+        Some((first, is_common))
+    }
+
+    pub fn edit_selection_strokes(&mut self, edit: impl Fn(&mut Stroke<WorldSpace>)) {
+        for (i, object) in self.objects.iter_mut().enumerate().filter(|(_, object)| object.base().is_selected) {
+            if let Some(mut stroke) = object.stroke() {
+                edit(&mut stroke);
+                object.set_stroke(stroke);
+                self.spatial_index.update(i, object.visual_rect());
+            }
+        }
+    }
+
+    // same as `selection_stroke`, but for the fill
+    pub fn selection_fill(&self) -> Option<(Fill, bool)> {
+        let mut fills = self.objects.iter().filter(|object| object.base().is_selected).filter_map(|object| object.fill());
+        let first = fills.next()?;
+        let is_common = fills.all(|fill| fill == first);
+        Some((first, is_common))
+    }
+
+    pub fn edit_selection_fills(&mut self, edit: impl Fn(&mut Fill)) {
+        for object in self.objects.iter_mut().filter(|object| object.base().is_selected) {
+            if let Some(mut fill) = object.fill() {
+                edit(&mut fill);
+                object.set_fill(fill);
+            }
+        }
+    }
+
+    // box around the selected objects that are not being clipped
+    pub fn selection_rect(&self) -> Option<Rectangle<WorldSpace>> {
+        self.selection_box().map(|(_, rect)| rect)
+    }
+
+    // same as `selection_stroke`, but for the picture adjustments
+    pub fn selection_adjustments(&self) -> Option<(ImageAdjustments, bool)> {
+        let mut adjustments = self.objects.iter().filter(|object| object.base().is_selected).filter_map(|object| object.adjustments());
+        let first = adjustments.next()?;
+        let is_common = adjustments.all(|a| a == first);
+        Some((first, is_common))
+    }
+
+    pub fn edit_selection_adjustments(&mut self, edit: impl Fn(&mut ImageAdjustments)) {
+        for object in self.objects.iter_mut().filter(|object| object.base().is_selected) {
+            if let Some(mut adjustments) = object.adjustments() {
+                edit(&mut adjustments);
+                object.set_adjustments(adjustments);
+            }
+        }
+    }
+
+    // The file links of the selected objects that have one, in the order of the objects.
+    pub fn selection_file_links(&self) -> Vec<FileLink> {
+        self.objects.iter().filter(|object| object.base().is_selected).filter_map(|object| object.file_link()).collect()
+    }
+
+    // `index` is the position in the list returned by `selection_file_links`.
+    pub fn edit_selection_file_link(&mut self, index: usize, edit: impl FnOnce(&mut FileLink)) {
+        let object = self.objects.iter_mut()
+                                 .filter(|object| object.base().is_selected && object.file_link().is_some())
+                                 .nth(index);
+        if let Some(object) = object {
+            if let Some(mut link) = object.file_link() {
+                edit(&mut link);
+                object.set_file_link(link);
+            }
+        }
+    }
+
+    pub fn is_watching_files(&self) -> bool {
+        self.is_watching_files
+    }
+
+    // same as `selection_stroke`, but for the playback of animations
+    pub fn selection_playback(&self) -> Option<(Playback, bool)> {
+        let mut playbacks = self.objects.iter().filter(|object| object.base().is_selected).filter_map(|object| object.playback());
+        let first = playbacks.next()?;
+        let is_common = playbacks.all(|playback| playback.is_playing == first.is_playing && playback.frame == first.frame);
+        Some((first, is_common))
+    }
+
+    pub fn edit_selection_playback(&mut self, edit: impl Fn(&mut Playback)) {
+        for object in self.objects.iter_mut().filter(|object| object.base().is_selected) {
+            if let Some(mut playback) = object.playback() {
+                edit(&mut playback);
+                object.set_playback(playback);
+            }
+        }
+    }
+
+    pub fn next_animation_frame(&self) -> Option<Duration> {
+        self.next_animation_frame
+    }
+
+    // The area drawn by `export_selection`, including the strokes that reach outside of the bounding rectangles.
+    pub fn export_rect(&self) -> Option<Rectangle<WorldSpace>> {
+        self.objects.iter()
+                    .filter(|object| object.base().is_selected)
+                    .map(|object| object.visual_rect())
+                    .reduce(|a, b| a.union(&b))
+    }
+
+    // Draws the selected objects without any markers, `rect` is scaled by `scale` and its top left corner ends up at the origin.
+    pub fn export_selection(&self, painter: &mut ExportPainter, rect: Rectangle<WorldSpace>, scale: f32) {
+        let camera = Camera { position: rect.p1, offset: Vector2::zero(), zoom: scale };
+        let mut world_painter = WorldPainter { screen_painter: painter };
+        for object in self.objects.iter().filter(|object| object.base().is_selected) {
+            object.draw_for_export(&mut world_painter, &camera);
+        }
+    }
+
+    // The center of the view if there is no position, e.g. when the mouse is outside of the canvas.
+    pub fn world_position(&self, screen_position: Option<Vector2<ScreenSpace>>) -> Vector2<WorldSpace> {
+        match screen_position {
+            Some(position) => self.camera.point_to_world_coordinates(position),
+            None           => self.view_rect().center(),
+        }
+    }
+
+    // moves and scales the selected objects so that `selection_rect` becomes `new_rect`
+    pub fn set_selection_rect(&mut self, new_rect: Rectangle<WorldSpace>) {
+        let Some((indices, rect)) = self.selection_box() else {
+            return;
+        };
+        for i in indices {
+            let object = &mut self.objects[i];
+            object.resize_to(rect.map_rect_to(object.get_bounding_rect(), &new_rect));
+            self.spatial_index.update(i, object.visual_rect());
+        }
+    }
+
+    // Mirrors the selected objects inside the box around the whole selection.
+    pub fn flip_selected_objects(&mut self, axis: Axis) {
+        let Some((indices, _)) = self.selection_box() else {
+            return;
+        };
+        let Some(rect) = indices.iter().map(|i| self.objects[*i].get_bounding_rect()).reduce(|a, b| a.union(&b)) else {
+            return;
+        };
+        for i in &indices {
+            self.flip_object(*i, axis, rect);
+        }
+        let positions = indices.iter().map(|i| self.well_ordered_bounding_rect(*i)).collect();
+        self.push_command(Command::Flip { indices, axis, rect, positions });
+    }
+
+    // Flips the objects again, each one about the flip box shifted by how far it was moved since the flip.
+    fn reflip_objects(&mut self, indices: &[usize], axis: Axis, rect: Rectangle<WorldSpace>, positions: &mut [Rectangle<WorldSpace>]) {
+        for (i, position) in indices.iter().zip(positions.iter_mut()) {
+            let moved = self.well_ordered_bounding_rect(*i).p1 - position.p1;
+            self.flip_object(*i, axis, rect.shifted_with(moved));
+            *position = self.well_ordered_bounding_rect(*i).shifted_with(-moved);
+        }
+    }
+
+    // Mirrors the object inside `rect`, doing it twice with the same `rect` restores it.
+    fn flip_object(&mut self, index: usize, axis: Axis, rect: Rectangle<WorldSpace>) {
+        let flipped_rect = match axis {
+            Axis::Horizontal => Rectangle { p1: Vector2::new(rect.p2.x, rect.p1.y), p2: Vector2::new(rect.p1.x, rect.p2.y) },
+            Axis::Vertical   => Rectangle { p1: Vector2::new(rect.p1.x, rect.p2.y), p2: Vector2::new(rect.p2.x, rect.p1.y) },
+        };
+        let object = &mut self.objects[index];
+        object.resize_to(rect.map_rect_to(object.get_bounding_rect(), &flipped_rect));
+        self.spatial_index.update(index, object.visual_rect());
+    }
+
+    fn well_ordered_bounding_rect(&self, index: usize) -> Rectangle<WorldSpace> {
+        let rect = self.objects[index].get_bounding_rect();
+        Rectangle::from_points_well_ordered(rect.p1, rect.p2)
+    }
+
+    // Turns each of the selected objects that can be rotated around its own center.
+    pub fn rotate_selected_objects(&mut self, rotation: Rotation) {
+        let mut indices = Vec::new();
+        for (i, object) in self.objects.iter_mut().enumerate() {
+            if object.base().is_selected && object.rotate_quarter_turn(rotation) {
+                self.spatial_index.update(i, object.visual_rect());
+                indices.push(i);
+            }
+        }
+        if !indices.is_empty() {
+            self.push_command(Command::Rotate { indices, rotation });
+        }
+    }
+
+    fn rotate_objects(&mut self, indices: &[usize], rotation: Rotation) {
+        for i in indices {
+            let object = &mut self.objects[*i];
+            object.rotate_quarter_turn(rotation);
+            self.spatial_index.update(*i, object.visual_rect());
+        }
+    }
+
+    fn push_command(&mut self, command: Command) {
+        self.undo_stack.push(command);
+        self.redo_stack.clear();
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(mut command) = self.undo_stack.pop() {
+            match &mut command {
+                Command::Flip { indices, axis, rect, positions } => {
+                    // flipping again undoes the flip
+                    self.reflip_objects(indices, *axis, *rect, positions);
+                },
+                Command::Rotate { indices, rotation } => {
+                    self.rotate_objects(indices, rotation.reversed());
+                },
+            }
+            self.redo_stack.push(command);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(mut command) = self.redo_stack.pop() {
+            match &mut command {
+                Command::Flip { indices, axis, rect, positions } => {
+                    self.reflip_objects(indices, *axis, *rect, positions);
+                },
+                Command::Rotate { indices, rotation } => {
+                    self.rotate_objects(indices, *rotation);
+                },
+            }
+            self.undo_stack.push(command);
+        }
+    }
+
+    // indices and well ordered bounding rectangles of the selected objects
+    fn selected_bounding_rects(&self) -> Vec<(usize, Rectangle<WorldSpace>)> {
+        self.objects.iter()
+                    .enumerate()
+                    .filter(|(_, object)| object.base().is_selected)
+                    .map(|(i, object)| {
+                        let rect = object.get_bounding_rect();
+                        (i, Rectangle::from_points_well_ordered(rect.p1, rect.p2))
+                    })
+                    .collect()
+    }
+
+    // The clip rectangle moves along, so that the same part of the object stays visible.
+    fn shift_object(&mut self, index: usize, delta: Vector2<WorldSpace>) {
+        let object = &mut self.objects[index];
+        object.shift_with(delta);
+        if let Some(clip_rect) = &mut object.base_mut().clip_rectangle {
+            clip_rect.p1 += delta;
+            clip_rect.p2 += delta;
+        }
+        self.spatial_index.update(index, object.visual_rect());
+    }
+
+    pub fn update(&mut self, input: UserInput, stroke: Stroke<WorldSpace>, fill: &Fill, view_width: f32, view_height: f32) -> Result<(), Error> {
         self.view_width = view_width;
         self.view_height = view_height;
         self.camera.offset = Vector2::new(view_width / 2.0, view_height / 2.0);
+        self.is_watching_files = false;
+        self.next_animation_frame = None;
+        for object in self.objects.iter_mut() {
+            self.is_watching_files |= object.watch_file();
+            if let Some(delay) = object.animate() {
+                self.next_animation_frame = Some(self.next_animation_frame.map_or(delay, |next| next.min(delay)));
+            }
+        }
+        self.poll_loading_objects();
 
         match input {
             UserInput::Pan { delta } => {
@@ -225,26 +937,105 @@ impl<P: ScreenPainter, IconType> Engine<P, IconType> {
                 }
             },
             UserInput::FinalizeClip => {
-                for object in self.objects.iter_mut() {
+                for (i, object) in self.objects.iter_mut().enumerate() {
                     if let Some(clip_rect) = object.base().clip_rectangle {
                         object.clip_to(clip_rect);
+                        self.spatial_index.update(i, object.visual_rect());
                     }
                     object.base_mut().clip_rectangle = None;
                     object.base_mut().is_selected = false;
                 }
             }
+            UserInput::Align { alignment } => {
+                self.align_selected_objects(alignment);
+            },
+            UserInput::Distribute { axis } => {
+                self.distribute_selected_objects(axis);
+            },
+            UserInput::Nudge { delta } => {
+                let delta = self.camera.distance_to_world_coordinates(delta);
+                for (i, _) in self.selected_bounding_rects() {
+                    self.shift_object(i, delta);
+                }
+            },
+            UserInput::Flip { axis } => {
+                self.flip_selected_objects(axis);
+            },
+            UserInput::Rotate { rotation } => {
+                self.rotate_selected_objects(rotation);
+            },
+            UserInput::Undo => {
+                self.undo();
+            },
+            UserInput::Redo => {
+                self.redo();
+            },
+            UserInput::CopyStyle => {
+                self.copy_style();
+            },
+            UserInput::PasteStyle => {
+                self.paste_style();
+            },
+            UserInput::MouseClick { position, button: MouseButton::Left, .. } if self.style_picker_is_active => {
+                self.pick_style(&input, position);
+            },
             _ => {
-                self.update_tools_and_objects(input, stroke)?;
+                self.update_tools_and_objects(input, stroke, fill)?;
             },
         }
 
         Ok(())
     }
 
-    fn update_tools_and_objects(&mut self, input: UserInput, stroke: Stroke<WorldSpace>) -> Result<(), String> {
+    pub fn is_loading(&self) -> bool {
+        self.objects.iter().any(|object| object.is_loading())
+    }
+
+    pub fn take_loading_errors(&mut self) -> Vec<Error> {
+        std::mem::take(&mut self.loading_errors)
+    }
+
+    fn poll_loading_objects(&mut self) {
+        let mut failed = Vec::new();
+        for (i, object) in self.objects.iter_mut().enumerate() {
+            match object.poll_loading() {
+                // the size can change once the content is known
+                Some(Ok(()))   => self.spatial_index.update(i, object.visual_rect()),
+                Some(Err(err)) => {
+                    self.loading_errors.push(err);
+                    // Linked objects stay as placeholders, so that they can be relinked to another file,
+                    // and objects that still show an earlier version keep it, e.g. after relinking to a broken file.
+                    if object.file_link().is_none_or(|link| !link.is_linked && link.is_missing) {
+                        failed.push(i);
+                    }
+                },
+                None           => {},
+            }
+        }
+        if failed.is_empty() {
+            return;
+        }
+
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.resize = None;
+        self.gradient_drag = None;
+        for i in failed.iter().rev() {
+            // going in reverse order to avoid shifting indeces
+            self.objects.swap_remove(*i);
+            self.spatial_index.swap_remove(*i);
+        }
+    }
+
+    fn update_tools_and_objects(&mut self, input: UserInput, stroke: Stroke<WorldSpace>, fill: &Fill) -> Result<(), Error> {
+        self.snap_indicators.clear();
+
         if let Some(tool_index) = self.selected_tool_index {
+            let tool_uses_snapping = self.tools.get(tool_index).is_some_and(|tool| tool.uses_snapping());
+            let tool_input = if tool_uses_snapping { self.snap_input(input) } else { input };
             if let Some(tool) = self.tools.get_mut(tool_index) {
-                if let Some(new_object) = tool.update(&input, stroke, &self.camera)? {
+                if let Some(new_object) = tool.update(&tool_input, stroke, fill, &self.camera)? {
+                    self.spatial_index.push(new_object.visual_rect());
                     self.objects.push(new_object);
                 }
             }
@@ -255,11 +1046,30 @@ impl<P: ScreenPainter, IconType> Engine<P, IconType> {
         }
         if input.mouse_is_up() {
             self.objects_are_dragged = false;
-            self.object_is_resized_by_vertex = None;
+            self.resize = None;
+            self.gradient_drag = None;
+            self.snap_residual = Vector2::zero();
         }
 
+        // The end of a radial gradient can be outside of the object, clicking it must not deselect the object.
+        let click_is_on_gradient_handle = match input {
+            UserInput::MouseClick { position, .. } => self.gradient_handle_under_mouse(self.camera.point_to_world_coordinates(position)).is_some(),
+            _                                      => false,
+        };
+
+        // Only the objects near the mouse can be under it, no need to hit-test the others.
+        let objects_near_mouse = match input.mouse_position() {
+            Some(position) => {
+                let p = self.camera.point_to_world_coordinates(position);
+                let margin = self.camera.size_to_world_coordinates(HIT_TOLERANCE);
+                self.spatial_index.query_rect(Rectangle::from_center_and_side_length(p, margin * 2.0))
+            },
+            None => Vec::new(),
+        };
+
         for (i, object) in self.objects.iter_mut().enumerate() {
             object.update(&input, &self.camera);
+            let is_under_mouse = objects_near_mouse.binary_search(&i).is_ok() && object.is_under_mouse(&self.camera);
 
             if self.selected_tool_index.is_none() {
                 if input == UserInput::SelectAll {
@@ -275,11 +1085,11 @@ impl<P: ScreenPainter, IconType> Engine<P, IconType> {
                     continue;
                 }
 
-                let left_click    = matches!(input, UserInput::MouseClick { button: MouseButton::Left, .. });
+                let left_click    = matches!(input, UserInput::MouseClick { button: MouseButton::Left, .. }) && !click_is_on_gradient_handle;
                 let shift_is_down = matches!(input, UserInput::MouseClick { is_shift_down: true, .. });
 
                 if left_click {
-                    if object.is_under_mouse() {
+                    if is_under_mouse {
                         if shift_is_down {
                             object.base_mut().is_selected = !object.base().is_selected;
                         }
@@ -293,92 +1103,348 @@ impl<P: ScreenPainter, IconType> Engine<P, IconType> {
                         }
                     }
                 }
-                if object.base().is_selected && object.is_under_mouse() {
+                if object.base().is_selected && is_under_mouse {
                     self.objects_are_dragged = true;
                 }
             }
         }
 
-        for object in self.objects.iter_mut() {
-            let Some(mouse_delta)    = input.mouse_delta()   .map(|d| self.camera.distance_to_world_coordinates(d)) else {break};
-            let Some(mouse_position) = input.mouse_position().map(|p| self.camera.point_to_world_coordinates(p))  else {break};
-
-            if object.base().is_selected {
-                let selection_marker_size = self.camera.size_to_world_coordinates(Self::SELECTION_MARKER_SIZE);
-
-                if let Some(clip_rect) = object.base().clip_rectangle {
-                    if let Some(vertex) = clip_rect.vertex_under_point(mouse_position, selection_marker_size) {
-                        self.object_is_resized_by_vertex = Some(vertex);
-                    }
-                    if let Some(vertex) = self.object_is_resized_by_vertex {
-                        let new_rect = clip_rect.resize_by_dragging_vertex(vertex, mouse_delta);
-                        if new_rect.width() > Self::MINIMUM_OBJECT_SIZE && new_rect.height() > Self::MINIMUM_OBJECT_SIZE {
-                            object.base_mut().clip_rectangle = Some(new_rect);
-                        }
-                    }
-                }
-                else {
-                    if let Some(vertex) = object.get_bounding_rect().vertex_under_point(mouse_position, selection_marker_size) {
-                        self.object_is_resized_by_vertex = Some(vertex);
-                    }
-                    if let Some(vertex) = self.object_is_resized_by_vertex {
-                        let new_rect = object.get_bounding_rect().resize_by_dragging_vertex(vertex, mouse_delta);
-                        if new_rect.width() > Self::MINIMUM_OBJECT_SIZE && new_rect.height() > Self::MINIMUM_OBJECT_SIZE {
-                            object.resize_to(new_rect);
-                        }
-                    }
-                    else if self.objects_are_dragged {
-                        object.shift_with(mouse_delta);
-                    }
-                }
-            }
+        if let UserInput::MouseMove { position, delta, is_shift_down, is_alt_down, .. } = input {
+            let mouse_delta    = self.camera.distance_to_world_coordinates(delta);
+            let mouse_position = self.camera.point_to_world_coordinates(position);
+            self.transform_selected_objects(mouse_position, mouse_delta, is_shift_down, is_alt_down);
         }
 
+        if !self.to_be_deleted.is_empty() {
+            self.undo_stack.clear();
+            self.redo_stack.clear();
+        }
         for i in self.to_be_deleted.iter().rev() {
             // going in reverse order to avoid shifting indeces
             self.objects.swap_remove(*i);
+            self.spatial_index.swap_remove(*i);
         }
         self.to_be_deleted.clear();
 
         Ok(())
     }
 
+    // Moves or resizes the selected objects (or their clip rectangles) when they are dragged.
+    fn transform_selected_objects(&mut self, mouse_position: Vector2<WorldSpace>, mouse_delta: Vector2<WorldSpace>, is_shift_down: bool, is_alt_down: bool) {
+        // the gradient handles are on top of the resize handles
+        if self.resize.is_none() && self.gradient_drag.is_none() {
+            self.gradient_drag = self.gradient_handle_under_mouse(mouse_position);
+        }
+        if self.resize.is_none() && self.gradient_drag.is_none() {
+            self.resize = self.resize_gesture_under_mouse(mouse_position);
+        }
+        if mouse_delta == Vector2::zero() {
+            return;
+        }
+
+        if let Some((i, handle)) = self.gradient_drag {
+            let frame = self.objects[i].get_bounding_rect();
+            if let Some(mut fill) = self.objects[i].fill() {
+                if let Some(gradient) = fill.gradient_mut() {
+                    let point = match handle {
+                        GradientHandle::Start => &mut gradient.start,
+                        GradientHandle::End   => &mut gradient.end,
+                    };
+                    *point = frame.point_to_unit(frame.point_from_unit(*point) + mouse_delta);
+                }
+                self.objects[i].set_fill(fill);
+            }
+        }
+        else if let Some(mut resize) = self.resize.take() {
+            self.resize_step(&mut resize, mouse_delta, is_shift_down, is_alt_down);
+            self.resize = Some(resize);
+        }
+        else if self.objects_are_dragged {
+            let mouse_delta = self.snap_movement(mouse_delta);
+            for (i, _) in self.selected_bounding_rects() {
+                if self.objects[i].base().clip_rectangle.is_none() {
+                    self.shift_object(i, mouse_delta);
+                }
+            }
+        }
+    }
+
+    // The gradient handles are only shown when a single object is selected.
+    fn gradient_handles(&self) -> Option<(usize, Vector2<WorldSpace>, Vector2<WorldSpace>)> {
+        let (indices, _) = self.selection_box()?;
+        let [i] = indices.as_slice() else {
+            return None;
+        };
+        let object = &self.objects[*i];
+        let fill = object.fill()?;
+        let gradient = fill.gradient()?;
+        let frame = object.get_bounding_rect();
+        Some((*i, frame.point_from_unit(gradient.start), frame.point_from_unit(gradient.end)))
+    }
+
+    fn gradient_handle_under_mouse(&self, mouse_position: Vector2<WorldSpace>) -> Option<(usize, GradientHandle)> {
+        let (i, start, end) = self.gradient_handles()?;
+        let radius = self.camera.size_to_world_coordinates(Self::GRADIENT_HANDLE_SIZE).value;
+        // the end first, because for new gradients the start is in the middle of the object, where its other handles are too
+        if (end - mouse_position).length() <= radius {
+            Some((i, GradientHandle::End))
+        }
+        else if (start - mouse_position).length() <= radius {
+            Some((i, GradientHandle::Start))
+        }
+        else {
+            None
+        }
+    }
+
+    fn resize_gesture_under_mouse(&self, mouse_position: Vector2<WorldSpace>) -> Option<ResizeGesture> {
+        let selection_marker_size = self.camera.size_to_world_coordinates(Self::SELECTION_MARKER_SIZE);
+        let gesture = |handle, target, rect| ResizeGesture {
+            handle,
+            target,
+            start_rect: rect,
+            current_rect: rect,
+            offset: Vector2::zero(),
+        };
+
+        // going in reverse order, so that the topmost clip rectangle wins
+        let clip_rect_gesture = self.objects.iter().enumerate().rev().find_map(|(i, object)| {
+            let clip_rect = object.base().clip_rectangle.filter(|_| object.base().is_selected)?;
+            clip_rect.handle_under_point(mouse_position, selection_marker_size)
+                     .map(|handle| gesture(handle, ResizeTarget::ClipRectangle(i), clip_rect))
+        });
+        clip_rect_gesture.or_else(|| {
+            let (indices, rect) = self.selection_box()?;
+            rect.handle_under_point(mouse_position, selection_marker_size)
+                .map(|handle| gesture(handle, ResizeTarget::Selection(indices), rect))
+        })
+    }
+
+    // The selected objects that are not being clipped and the box around them that has the resize handles.
+    // A single object keeps its own bounding rectangle, even if it is not well ordered, so that the handles stay where they were.
+    fn selection_box(&self) -> Option<(Vec<usize>, Rectangle<WorldSpace>)> {
+        let indices = self.objects.iter()
+                                  .enumerate()
+                                  .filter(|(_, object)| object.base().is_selected && object.base().clip_rectangle.is_none())
+                                  .map(|(i, _)| i)
+                                  .collect::<Vec<_>>();
+        let rect = match indices.as_slice() {
+            []  => return None,
+            [i] => self.objects[*i].get_bounding_rect(),
+            _   => indices.iter().map(|i| self.objects[*i].get_bounding_rect()).reduce(|a, b| a.union(&b))?,
+        };
+        Some((indices, rect))
+    }
+
+    // The new rectangle is always computed from the one at the start of the gesture,
+    // so steps that are skipped (because they'd collapse the object) don't make the handle drift away from the mouse.
+    fn resize_step(&mut self, resize: &mut ResizeGesture, mouse_delta: Vector2<WorldSpace>, is_shift_down: bool, is_alt_down: bool) {
+        resize.offset += mouse_delta;
+
+        let handle_position = resize.start_rect.handle(resize.handle) + resize.offset;
+        let tolerance = self.camera.size_to_world_coordinates(Self::SNAP_TOLERANCE);
+        let snap_result = self.snapping.snap(&[handle_position], &self.snap_targets(), tolerance);
+        self.snap_indicators = snap_result.indicators;
+
+        let keeps_aspect_ratio = match &resize.target {
+            ResizeTarget::Selection(indices) => indices.iter().all(|i| self.objects[*i].keeps_aspect_ratio()),
+            ResizeTarget::ClipRectangle(_)   => false,
+        };
+        // shift toggles the default behavior
+        let keep_aspect_ratio = keeps_aspect_ratio != is_shift_down;
+        let new_rect = resize.start_rect.resize_by_dragging_handle(resize.handle, resize.offset + snap_result.correction, keep_aspect_ratio, is_alt_down);
+
+        let minimum_size = self.camera.size_to_world_coordinates(Self::MINIMUM_OBJECT_SIZE).value;
+        let collapses = |new_size: Number<WorldSpace>, start_size: Number<WorldSpace>| new_size.value.abs() < minimum_size && start_size.value.abs() >= minimum_size;
+        if collapses(new_rect.width(), resize.start_rect.width()) || collapses(new_rect.height(), resize.start_rect.height()) {
+            return;
+        }
+
+        match &resize.target {
+            ResizeTarget::Selection(indices) => {
+                for i in indices.iter() {
+                    let object = &mut self.objects[*i];
+                    object.resize_to(resize.current_rect.map_rect_to(object.get_bounding_rect(), &new_rect));
+                    self.spatial_index.update(*i, object.visual_rect());
+                }
+            },
+            ResizeTarget::ClipRectangle(i) => {
+                self.objects[*i].base_mut().clip_rectangle = Some(Rectangle::from_points_well_ordered(new_rect.p1, new_rect.p2));
+            },
+        }
+        resize.current_rect = new_rect;
+    }
+
+    // Returns how much the selection should actually be moved when the mouse moves with `delta`.
+    // The bounding box of the selection snaps to the targets, and the difference is remembered
+    // so that the selection can be pulled away from the target again.
+    fn snap_movement(&mut self, delta: Vector2<WorldSpace>) -> Vector2<WorldSpace> {
+        let selection_rect = self.selected_bounding_rects().into_iter().map(|(_, rect)| rect).reduce(|a, b| a.union(&b));
+        let Some(selection_rect) = selection_rect else {
+            return delta;
+        };
+
+        let desired_delta = self.snap_residual + delta;
+        let mut points = selection_rect.vertices().to_vec();
+        points.push(selection_rect.center());
+        let points = points.into_iter().map(|p| p + desired_delta).collect::<Vec<_>>();
+        let tolerance = self.camera.size_to_world_coordinates(Self::SNAP_TOLERANCE);
+        let result = self.snapping.snap(&points, &self.snap_targets(), tolerance);
+
+        self.snap_residual = -result.correction;
+        self.snap_indicators = result.indicators;
+        desired_delta + result.correction
+    }
+
+    fn snap_input(&mut self, input: UserInput) -> UserInput {
+        let Some(position) = input.mouse_position() else {
+            return input;
+        };
+        let p = self.camera.point_to_world_coordinates(position);
+        let tolerance = self.camera.size_to_world_coordinates(Self::SNAP_TOLERANCE);
+        let result = self.snapping.snap(&[p], &self.snap_targets(), tolerance);
+
+        self.snap_indicators = result.indicators;
+        input.with_mouse_position(self.camera.point_to_screen_coordinates(p + result.correction))
+    }
+
+    // snap points of the visible objects that are not selected
+    fn snap_targets(&self) -> Vec<Vector2<WorldSpace>> {
+        self.spatial_index.query_rect(self.view_rect())
+                          .into_iter()
+                          .map(|i| &self.objects[i])
+                          .filter(|object| !object.base().is_selected)
+                          .flat_map(|object| object.snap_points())
+                          .collect()
+    }
+
+    fn rect_to_screen_coordinates(&self, rect: Rectangle<WorldSpace>) -> Rectangle<ScreenSpace> {
+        Rectangle {
+            p1: self.camera.point_to_screen_coordinates(rect.p1),
+            p2: self.camera.point_to_screen_coordinates(rect.p2),
+        }
+    }
+
+    fn view_rect(&self) -> Rectangle<WorldSpace> {
+        Rectangle {
+            p1: self.camera.point_to_world_coordinates(Vector2::zero()),
+            p2: self.camera.point_to_world_coordinates(Vector2::new(self.view_width, self.view_height)),
+        }
+    }
+
     pub fn draw(&self, screen_painter: &mut P, background_color: Color) {
         screen_painter.draw_rectangle_filled(Rectangle::from_point_and_size(Vector2::zero(), Number::new(self.view_width), Number::new(self.view_height)), background_color, None);
 
-        for object in self.objects.iter() {
+        if self.snapping.grid_is_visible {
+            self.draw_grid(screen_painter, background_color);
+        }
+
+        let visible_objects = self.spatial_index.query_rect(self.view_rect());
+        let selection_marker_stroke = Stroke::new(background_color.inverse(), Number::<ScreenSpace>::new(2.0));
+
+        for (i, object) in self.objects.iter().enumerate() {
+            // Selected objects are always drawn because their clip rectangle can reach outside of their bounding rectangle.
+            if visible_objects.binary_search(&i).is_err() && !object.base().is_selected {
+                continue;
+            }
             let mut world_painter = WorldPainter { screen_painter };
             object.draw(&mut world_painter, &self.camera);
             if object.base().is_selected {
-                let world_rect = object.base().clip_rectangle.unwrap_or(object.get_bounding_rect());
-                let screen_rect = Rectangle {
-                    p1: self.camera.point_to_screen_coordinates(world_rect.p1),
-                    p2: self.camera.point_to_screen_coordinates(world_rect.p2),
-                };
-                let selection_marker_stroke = Stroke::new(background_color.inverse(), Number::<ScreenSpace>::new(2.0));
-                screen_painter.draw_rectangle(screen_rect, selection_marker_stroke);
-                for vertex in screen_rect.vertices() {
-                    if object.base().clip_rectangle.is_some() {
-                        screen_painter.draw_rectangle_filled(Rectangle::from_center_and_side_length(vertex, Self::SELECTION_MARKER_SIZE * 2.0),
+                if let Some(clip_rect) = object.base().clip_rectangle {
+                    let screen_rect = self.rect_to_screen_coordinates(clip_rect);
+                    screen_painter.draw_rectangle(screen_rect, selection_marker_stroke);
+                    for handle in RectangleHandle::ALL {
+                        screen_painter.draw_rectangle_filled(Rectangle::from_center_and_side_length(screen_rect.handle(handle), Self::SELECTION_MARKER_SIZE * 2.0),
                                                              selection_marker_stroke.color,
                                                              None);
                     }
-                    else {
-                        screen_painter.draw_circle(vertex, Self::SELECTION_MARKER_SIZE, selection_marker_stroke);
-                    }
+                }
+                else {
+                    // the handles are only drawn around the whole selection, see below
+                    screen_painter.draw_rectangle(self.rect_to_screen_coordinates(object.get_bounding_rect()), Stroke::new(selection_marker_stroke.color, Number::new(1.0)));
                 }
             }
         }
 
+        if let Some((_, selection_rect)) = self.selection_box() {
+            let screen_rect = self.rect_to_screen_coordinates(selection_rect);
+            screen_painter.draw_rectangle(screen_rect, selection_marker_stroke);
+            for handle in RectangleHandle::ALL {
+                screen_painter.draw_circle(screen_rect.handle(handle), Self::SELECTION_MARKER_SIZE, selection_marker_stroke);
+            }
+        }
+
+        if let Some((_, start, end)) = self.gradient_handles() {
+            let start = self.camera.point_to_screen_coordinates(start);
+            let end   = self.camera.point_to_screen_coordinates(end);
+            screen_painter.draw_line(start, end, Stroke::new(selection_marker_stroke.color, Number::new(1.0)));
+            for p in [start, end] {
+                screen_painter.draw_circle(p, Self::GRADIENT_HANDLE_SIZE, selection_marker_stroke);
+                screen_painter.draw_circle(p, Self::GRADIENT_HANDLE_SIZE * 0.5, Stroke::new(background_color, Number::new(1.0)));
+            }
+        }
+
         if let Some(tool_index) = self.selected_tool_index {
             let mut world_painter = WorldPainter { screen_painter };
             if let Some(tool) = self.tools.get(tool_index) {
                 tool.draw(&mut world_painter, background_color, &self.camera);
             }
         }
+
+        self.draw_snap_indicators(screen_painter);
+    }
+
+    fn draw_grid(&self, screen_painter: &mut P, background_color: Color) {
+        let spacing = self.snapping.grid_spacing;
+        if self.camera.size_to_screen_coordinates(spacing) < Self::MINIMUM_GRID_SPACING_ON_SCREEN {
+            // The lines would be so dense that they'd just cover the whole background.
+            return;
+        }
+
+        let view_rect = self.view_rect();
+        let stroke = Stroke::new(background_color.mixed_with(background_color.inverse(), 0.15), Number::<ScreenSpace>::new(1.0));
+
+        let mut x = (view_rect.p1.x / spacing.value).ceil() * spacing.value;
+        while x <= view_rect.p2.x {
+            screen_painter.draw_line(self.camera.point_to_screen_coordinates(Vector2::new(x, view_rect.p1.y)),
+                                     self.camera.point_to_screen_coordinates(Vector2::new(x, view_rect.p2.y)),
+                                     stroke);
+            x += spacing.value;
+        }
+        let mut y = (view_rect.p1.y / spacing.value).ceil() * spacing.value;
+        while y <= view_rect.p2.y {
+            screen_painter.draw_line(self.camera.point_to_screen_coordinates(Vector2::new(view_rect.p1.x, y)),
+                                     self.camera.point_to_screen_coordinates(Vector2::new(view_rect.p2.x, y)),
+                                     stroke);
+            y += spacing.value;
+        }
+    }
+
+    fn draw_snap_indicators(&self, screen_painter: &mut P) {
+        let stroke = Stroke::new(Self::SNAP_INDICATOR_COLOR, Number::<ScreenSpace>::new(1.0));
+        let view_rect = self.view_rect();
+
+        for indicator in self.snap_indicators.iter() {
+            match *indicator {
+                SnapIndicator::Point(p) => {
+                    let center = self.camera.point_to_screen_coordinates(p);
+                    screen_painter.draw_rectangle(Rectangle::from_center_and_side_length(center, Self::SELECTION_MARKER_SIZE * 2.0), stroke);
+                },
+                SnapIndicator::VerticalGuide { x } => {
+                    screen_painter.draw_line(self.camera.point_to_screen_coordinates(Vector2::new(x, view_rect.p1.y)),
+                                             self.camera.point_to_screen_coordinates(Vector2::new(x, view_rect.p2.y)),
+                                             stroke);
+                },
+                SnapIndicator::HorizontalGuide { y } => {
+                    screen_painter.draw_line(self.camera.point_to_screen_coordinates(Vector2::new(view_rect.p1.x, y)),
+                                             self.camera.point_to_screen_coordinates(Vector2::new(view_rect.p2.x, y)),
+                                             stroke);
+                },
+            }
+        }
     }
 
-    pub fn tools_iter(&self) -> ToolIterator<P, IconType> {
+    pub fn tools_iter(&self) -> ToolIterator<'_, P, IconType> {
         ToolIterator { tools: &self.tools, index: 0 }
     }
 
@@ -395,3 +1461,58 @@ impl<P: ScreenPainter, IconType> Engine<P, IconType> {
         self.selected_tool_index
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::egui_painter::EguiPainter;
+    use crate::scene_file::load_objects;
+
+    fn engine_with_lines(lines: &[(Vector2<WorldSpace>, Vector2<WorldSpace>)]) -> Engine<EguiPainter, ()> {
+        let mut writer = SceneWriter::new();
+        for (start, end) in lines {
+            writer.begin_object("line");
+            writer.point(*start);
+            writer.point(*end);
+            writer.stroke(Stroke::new(Color::from_rgb(0, 0, 0), Number::new(1.0)));
+        }
+        let mut engine = Engine::new(Vec::new());
+        engine.replace_objects(load_objects(&writer.finish().unwrap()).unwrap());
+        engine
+    }
+
+    fn saved(engine: &Engine<EguiPainter, ()>) -> String {
+        let mut writer = SceneWriter::new();
+        engine.save_objects(&mut writer);
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn flip_is_undone_in_place_after_a_move() {
+        let lines = [
+            (Vector2::new(0.0, 0.0), Vector2::new(10.0, 5.0)),
+            (Vector2::new(20.0, 0.0), Vector2::new(30.0, 10.0)),
+        ];
+        let delta = Vector2::new(100.0, 50.0);
+        let moved = lines.map(|(start, end)| (start + delta, end + delta));
+
+        let mut engine = engine_with_lines(&lines);
+        for object in engine.objects.iter_mut() {
+            object.base_mut().is_selected = true;
+        }
+        engine.flip_selected_objects(Axis::Horizontal);
+        let flipped = saved(&engine);
+        for i in 0..lines.len() {
+            engine.shift_object(i, delta);
+        }
+        engine.undo();
+        assert_eq!(saved(&engine), saved(&engine_with_lines(&moved)));
+
+        for i in 0..lines.len() {
+            engine.shift_object(i, -delta);
+        }
+        engine.redo();
+        assert_eq!(saved(&engine), flipped);
+    }
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+
+// Everything that can go wrong while loading or saving, so that the UI can react differently to different problems.
+#[derive(Debug)]
+pub enum Error {
+    // reading or writing a file failed, e.g. it doesn't exist or we don't have permission
+    Io {
+        path: Option<PathBuf>,
+        source: std::io::Error,
+    },
+    // the file is not in a format we can read
+    UnsupportedFormat {
+        path: Option<PathBuf>,
+        source: Option<image::ImageError>,
+    },
+    // the format is supported, but the contents are broken
+    Decode {
+        path: Option<PathBuf>,
+        source: Option<image::ImageError>,
+    },
+    Encode {
+        source: image::ImageError,
+    },
+    // the image exceeds the memory or size limits of the decoder
+    TooLarge {
+        path: Option<PathBuf>,
+        source: image::ImageError,
+    },
+    InvalidScene {
+        line: usize,
+        message: String,
+    },
+    InvalidPalette {
+        path: Option<PathBuf>,
+        message: String,
+    },
+    // a dropped file without a path, this only happens on the web
+    MissingPath,
+    Clipboard {
+        source: arboard::Error,
+    },
+}
+
+impl Error {
+    pub fn io(path: &Path, source: std::io::Error) -> Self {
+        Self::Io { path: Some(path.to_path_buf()), source }
+    }
+
+    // Sorts the errors of the image crate into our categories.
+    pub fn image(path: Option<&Path>, source: image::ImageError) -> Self {
+        let path = path.map(Path::to_path_buf);
+        match source {
+            image::ImageError::IoError(source)     => Self::Io { path, source },
+            image::ImageError::Unsupported(_)      => Self::UnsupportedFormat { path, source: Some(source) },
+            image::ImageError::Limits(_)           => Self::TooLarge { path, source },
+            image::ImageError::Encoding(_)         => Self::Encode { source },
+            image::ImageError::Decoding(_)
+            | image::ImageError::Parameter(_)      => Self::Decode { path, source: Some(source) },
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Io { path, .. }
+            | Self::UnsupportedFormat { path, .. }
+            | Self::Decode { path, .. }
+            | Self::TooLarge { path, .. }
+            | Self::InvalidPalette { path, .. }  => path.as_deref(),
+            _                                    => None,
+        }
+    }
+
+    // What the user can do about the error.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Self::Io { source, .. } if source.kind() == std::io::ErrorKind::NotFound         => Some("Check that the file still exists."),
+            Self::Io { source, .. } if source.kind() == std::io::ErrorKind::PermissionDenied => Some("Check that you have permission to access the file."),
+            Self::UnsupportedFormat { .. } => Some("Convert the picture to a common format like PNG or JPEG."),
+            Self::Decode { .. }            => Some("The file may be damaged or only partially downloaded."),
+            Self::TooLarge { .. }          => Some("Scale the picture down in another program and try again."),
+            Self::Clipboard { source: arboard::Error::ClipboardOccupied } => Some("Another program is using the clipboard, try again in a moment."),
+            _                              => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, .. }                => match path {
+                Some(path) => write!(f, "Could not access \"{}\".", path.display()),
+                None       => write!(f, "Could not access the data."),
+            },
+            Self::UnsupportedFormat { path, .. } => match path {
+                Some(path) => write!(f, "\"{}\" is not in a supported format.", path.display()),
+                None       => write!(f, "The data is not in a supported format."),
+            },
+            Self::Decode { path, .. }            => match path {
+                Some(path) => write!(f, "Could not read the picture \"{}\".", path.display()),
+                None       => write!(f, "Could not read the picture."),
+            },
+            Self::Encode { .. }                  => write!(f, "Could not encode the picture."),
+            Self::TooLarge { path, .. }          => match path {
+                Some(path) => write!(f, "The picture \"{}\" is too large.", path.display()),
+                None       => write!(f, "The picture is too large."),
+            },
+            Self::InvalidScene { line, message } => write!(f, "Error on line {} of the scene file: {}", line, message),
+            Self::InvalidPalette { path, message } => match path {
+                Some(path) => write!(f, "Invalid palette file \"{}\": {}", path.display(), message),
+                None       => write!(f, "Invalid palette: {}", message),
+            },
+            Self::MissingPath                    => write!(f, "Error accessing the dropped file."),
+            Self::Clipboard { .. }               => write!(f, "Could not access the clipboard."),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. }                              => Some(source),
+            Self::UnsupportedFormat { source: Some(source), .. }
+            | Self::Decode { source: Some(source), .. }          => Some(source),
+            Self::Encode { source }
+            | Self::TooLarge { source, .. }                      => Some(source),
+            Self::Clipboard { source }                           => Some(source),
+            _                                                    => None,
+        }
+    }
+}
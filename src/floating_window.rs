@@ -41,12 +41,14 @@ impl FloatingWindow {
                 window = window.anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO);
             }
 
+            // Clicking an entry of a combo box popup must not count as clicking outside the window.
+            let popup_was_open = ctx.memory(|memory| memory.any_popup_open());
             let response = window.show(ctx, f);
 
             if let Some(r) = response {
                 // Have to wait a little bit to not register the click that opened this window as clicking elsewhere.
                 let been_open_for_a_while = Instant::now().duration_since(*opening_time) > Duration::from_millis(100);
-                if r.response.clicked_elsewhere() && been_open_for_a_while {
+                if r.response.clicked_elsewhere() && been_open_for_a_while && !popup_was_open {
                     self.is_open = false;
                 }
             }
@@ -23,3 +23,15 @@ mod paint_object;
 mod color_selector;
 mod egui_painter;
 mod floating_window;
+mod spatial_index;
+mod snapping;
+mod properties_panel;
+mod storage;
+mod palette;
+mod error;
+mod toasts;
+mod scene_file;
+mod autosave;
+mod raster_painter;
+mod svg_painter;
+mod clipboard;
@@ -1,6 +1,8 @@
 use crate::primitives::*;
 use crate::engine::*;
+use crate::error::Error;
 use crate::egui_painter::EguiPainter;
+use crate::scene_file::{SceneWriter, SceneReader};
 use eframe::egui;
 
 
@@ -15,6 +17,25 @@ pub struct FreehandCurve {
     mouse_pos: Vector2<WorldSpace>,
 }
 
+impl FreehandCurve {
+    pub const KIND: &str = "curve";
+
+    pub fn load(reader: &mut SceneReader) -> Result<Self, Error> {
+        let mut curve = FreehandCurveTool::new_curve();
+        let rect: Rectangle<WorldSpace> = reader.rect()?;
+        curve.min_x = rect.p1.x;
+        curve.min_y = rect.p1.y;
+        curve.max_x = rect.p2.x;
+        curve.max_y = rect.p2.y;
+        curve.stroke = Some(reader.stroke()?);
+        let point_count = reader.count()?;
+        for _ in 0..point_count {
+            curve.points.push(reader.point()?);
+        }
+        Ok(curve)
+    }
+}
+
 impl PaintObject<EguiPainter> for FreehandCurve {
     fn base(&self) -> &PaintObjectCommon {
         &self.base
@@ -32,21 +53,33 @@ impl PaintObject<EguiPainter> for FreehandCurve {
     
     fn draw<'a>(&self, painter: &mut WorldPainter<'a, EguiPainter>, camera: &Camera) {
         if let Some(stroke) = self.stroke {
-            for p1p2 in self.points.windows(2) {
-                painter.draw_line(p1p2[0], p1p2[1], stroke, camera);
-            }
+            painter.draw_polyline(&self.points, stroke, camera);
+        }
+    }
+
+    fn draw_for_export<'a>(&self, painter: &mut WorldPainter<'a, ExportPainter>, camera: &Camera) {
+        if let Some(stroke) = self.stroke {
+            painter.draw_polyline(&self.points, stroke, camera);
         }
     }
     
-    fn is_under_mouse(&self) -> bool {
-        if self.get_bounding_rect().contains_point(self.mouse_pos) {
-            for point in self.points.iter() {
-                if (*point - self.mouse_pos).length_squared() < 25.0 {
-                    return true;
-                }
-            }
+    fn is_under_mouse(&self, camera: &Camera) -> bool {
+        let Some(stroke) = self.stroke else {
+            return false;
+        };
+        let tolerance = camera.size_to_world_coordinates(HIT_TOLERANCE) + stroke.thickness * 0.5;
+        if !self.get_bounding_rect().expanded_by(tolerance).contains_point(self.mouse_pos) {
+            return false;
+        }
+        if let [point] = self.points.as_slice() {
+            return (*point - self.mouse_pos).length() < tolerance.value;
         }
-        return false;
+        self.points.windows(2).any(|p1p2| self.mouse_pos.distance_to_segment(p1p2[0], p1p2[1]) < tolerance.value)
+    }
+
+    fn color_at(&self, point: Vector2<WorldSpace>) -> Option<Color> {
+        let stroke = self.stroke?;
+        stroke_contains_point(&self.points, stroke, point).then_some(stroke.color)
     }
 
     fn get_bounding_rect(&self) -> Rectangle<WorldSpace> {
@@ -58,7 +91,7 @@ impl PaintObject<EguiPainter> for FreehandCurve {
 
     fn shift_with(&mut self, p: Vector2<WorldSpace>) {
         for point in self.points.iter_mut() {
-            *point = *point + p;
+            *point += p;
         }
         self.min_x += p.x;
         self.min_y += p.y;
@@ -69,10 +102,10 @@ impl PaintObject<EguiPainter> for FreehandCurve {
     fn resize_to(&mut self, new_size: Rectangle<WorldSpace>) {
         let old_size = self.get_bounding_rect();
         for point in self.points.iter_mut() {
-            point.x = new_size.p1.x + (point.x - old_size.p1.x) * (new_size.p2.x - new_size.p1.x) / (old_size.p2.x - old_size.p1.x);
-            point.y = new_size.p1.y + (point.y - old_size.p1.y) * (new_size.p2.y - new_size.p1.y) / (old_size.p2.y - old_size.p1.y);
+            *point = old_size.map_point_to(*point, &new_size);
         }
 
+        let new_size = Rectangle::from_points_well_ordered(new_size.p1, new_size.p2);
         self.min_x = new_size.p1.x;
         self.min_y = new_size.p1.y;
         self.max_x = new_size.p2.x;
@@ -95,6 +128,37 @@ impl PaintObject<EguiPainter> for FreehandCurve {
         self.max_x = new_size.p2.x;
         self.max_y = new_size.p2.y;
     }
+
+    fn save(&self, writer: &mut SceneWriter) {
+        // curves only exist without a stroke while they are being drawn
+        let Some(stroke) = self.stroke else {
+            return;
+        };
+        writer.begin_object(Self::KIND);
+        writer.rect(self.get_bounding_rect());
+        writer.stroke(stroke);
+        writer.count(self.points.len());
+        for point in self.points.iter() {
+            writer.point(*point);
+        }
+    }
+
+    fn stroke(&self) -> Option<Stroke<WorldSpace>> {
+        self.stroke
+    }
+
+    fn set_stroke(&mut self, stroke: Stroke<WorldSpace>) {
+        self.stroke = Some(stroke);
+    }
+
+    fn snap_points(&self) -> Vec<Vector2<WorldSpace>> {
+        let rect = self.get_bounding_rect();
+        let mut points = rect.vertices().to_vec();
+        points.push(rect.center());
+        points.extend(self.points.first());
+        points.extend(self.points.last());
+        points
+    }
 }
 
 
@@ -128,7 +192,7 @@ impl Default for FreehandCurveTool {
 }
 
 impl Tool<EguiPainter, egui::ImageSource<'static>> for FreehandCurveTool {
-    fn update(&mut self, input: &UserInput, stroke: Stroke<WorldSpace>, camera: &Camera) -> Result<Option<Box<dyn PaintObject<EguiPainter>>>, String> {
+    fn update(&mut self, input: &UserInput, stroke: Stroke<WorldSpace>, _fill: &Fill, camera: &Camera) -> Result<Option<Box<dyn PaintObject<EguiPainter>>>, Error> {
         self.curve.stroke = Some(stroke);
         if let UserInput::MouseMove { position, button: MouseButton::Left, is_shift_down: false, .. } = input {
             let p = camera.point_to_world_coordinates(*position);
@@ -149,12 +213,12 @@ impl Tool<EguiPainter, egui::ImageSource<'static>> for FreehandCurveTool {
                 }
             }
         }
-        else if self.curve.points.len() > 0 {
+        else if !self.curve.points.is_empty() {
             let new_object = std::mem::replace(&mut self.curve, Self::new_curve());
             return Ok(Some(Box::new(new_object)));
         }
 
-        return Ok(None);
+        Ok(None)
     }
 
     fn draw<'a>(&self, painter: &mut WorldPainter<'a, EguiPainter>, _bg_color: Color, camera: &Camera) {
@@ -168,4 +232,9 @@ impl Tool<EguiPainter, egui::ImageSource<'static>> for FreehandCurveTool {
     fn icon(&self) -> egui::ImageSource<'static> {
         self.icon.clone()
     }
+
+    fn uses_snapping(&self) -> bool {
+        // snapping every point would make the curve jagged
+        false
+    }
 }
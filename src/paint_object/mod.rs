@@ -1,3 +1,4 @@
 pub mod straight_line;
 pub mod freehand_curve;
 pub mod picture;
+pub mod shape;
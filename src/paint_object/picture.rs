@@ -1,64 +1,404 @@
 use std::cell::OnceCell;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant, SystemTime};
 use image;
+use image::{AnimationDecoder, GenericImageView, ImageDecoder};
 use eframe::egui;
 use rfd::FileDialog;
-use crate::egui_painter::EguiPainter;
+use crate::egui_painter::{EguiPainter, PictureTexture};
 use crate::primitives::*;
 use crate::engine::*;
+use crate::error::Error;
+use crate::scene_file::{SceneWriter, SceneReader};
 
 
 pub struct Picture {
     base: PaintObjectCommon,
-    bounding_rect: Rectangle<WorldSpace>,
-    image: image::DynamicImage,
+    bounding_rect: Rectangle<WorldSpace>, // always well ordered, mirroring is stored in `flip`
+    flip: Flip,
+    image: Option<Arc<image::DynamicImage>>, // shared with the autosave thread, `None` while it's being decoded
+    frames: Vec<AnimationFrame>, // empty if the picture isn't animated, otherwise the first one is `image`
+    current_frame: usize,
+    is_playing: bool,
+    frame_start: Instant, // when the current frame was first shown
     image_name: String,
-    texture: OnceCell<egui::TextureHandle>,
+    loading: Option<ImageLoading>,
+    link: Option<Link>, // `None` for pictures that don't come from a file, e.g. pasted ones
+    adjustments: ImageAdjustments,
+    quarter_turns: u8, // clockwise, applied before `flip`
+    // what is drawn, it can lag behind `image`, `adjustments` and `quarter_turns` while the next one is computed
+    adjusted: Option<AdjustedImage>,
+    adjusting: Option<ImageAdjusting>,
+    textures: Vec<OnceCell<PictureTexture>>, // one for each of the adjusted images
+
     mouse_pos: Vector2<WorldSpace>,
 }
 
+
+struct Link {
+    path: PathBuf,
+    is_linked: bool,
+    // when the file was last changed before it was loaded, `None` if it couldn't be read
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl Link {
+    // the file an embedded picture came from, so that it can be linked again
+    fn embedded(path: PathBuf) -> Self {
+        Self { path, is_linked: false, modified: None, last_check: Instant::now() }
+    }
+}
+
+
+struct AnimationFrame {
+    image: Arc<image::DynamicImage>,
+    delay: Duration,
+}
+
+
+// Copies of the frames with the adjustments and the rotation applied, they are the same images if there are none.
+struct AdjustedImage {
+    source: Arc<image::DynamicImage>, // the first frame
+    adjustments: ImageAdjustments,
+    quarter_turns: u8,
+    images: Vec<Arc<image::DynamicImage>>,
+}
+
+
+// Adjusting large pictures takes too long for doing it while dragging a slider, so it happens on a worker thread.
+struct ImageAdjusting {
+    receiver: Receiver<AdjustedImage>,
+    start_time: Instant,
+}
+
+
+// A picture that is being decoded on a worker thread, so that large files don't freeze the UI.
+struct ImageLoading {
+    receiver: Receiver<Result<Vec<AnimationFrame>, Error>>,
+    start_time: Instant,
+    // placeholders that weren't given a size by the user get the size of the image
+    fit_to_image: bool,
+}
+
 impl Picture {
-    // OK(Some(picture)): the dropped file is a supported picture and we could read it sucessfully
+    pub const KIND: &str = "picture";
+    // linked pictures only store the path of the file
+    pub const LINKED_KIND: &str = "linked-picture";
+    const PLACEHOLDER_WIDTH: Number<WorldSpace> = Number::<WorldSpace>::new(200.0);
+    const PLACEHOLDER_HEIGHT: Number<WorldSpace> = Number::<WorldSpace>::new(150.0);
+    const SPINNER_RADIUS: Number<ScreenSpace> = Number::<ScreenSpace>::new(12.0);
+    // browsers show frames that have no proper delay for this long, so the files are made with that in mind
+    const MINIMUM_FRAME_DELAY: Duration = Duration::from_millis(20);
+    const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+    fn new(bounding_rect: Rectangle<WorldSpace>, image: image::DynamicImage, image_name: String) -> Self {
+        let image = Arc::new(image);
+        Self {
+            base: PaintObjectCommon::default(),
+            bounding_rect: Rectangle::from_points_well_ordered(bounding_rect.p1, bounding_rect.p2),
+            flip: Flip::default(),
+            adjusted: Some(AdjustedImage { source: Arc::clone(&image), adjustments: ImageAdjustments::default(), quarter_turns: 0, images: vec![Arc::clone(&image)] }),
+            image: Some(image),
+            frames: Vec::new(),
+            current_frame: 0,
+            is_playing: true,
+            frame_start: Instant::now(),
+            image_name,
+            loading: None,
+            link: None,
+            adjustments: ImageAdjustments::default(),
+            quarter_turns: 0,
+            adjusting: None,
+            textures: vec![OnceCell::new()],
+            mouse_pos: Vector2::zero(),
+        }
+    }
+
+    // A placeholder that shows a loading indicator until the file is decoded.
+    // If `bounding_rect` is `None` then a default size is used until the size of the image is known.
+    fn load_in_background(path: PathBuf, top_left: Vector2<WorldSpace>, bounding_rect: Option<Rectangle<WorldSpace>>, is_linked: bool) -> Self {
+        let rect = bounding_rect.unwrap_or_else(|| Rectangle::from_point_and_size(top_left, Self::PLACEHOLDER_WIDTH, Self::PLACEHOLDER_HEIGHT));
+        let mut picture = Self {
+            base: PaintObjectCommon::default(),
+            bounding_rect: Rectangle::from_points_well_ordered(rect.p1, rect.p2),
+            flip: Flip::default(),
+            image: None,
+            frames: Vec::new(),
+            current_frame: 0,
+            is_playing: true,
+            frame_start: Instant::now(),
+            image_name: path.to_string_lossy().into_owned(),
+            loading: None,
+            link: Some(Link { path, is_linked, modified: None, last_check: Instant::now() }),
+            adjustments: ImageAdjustments::default(),
+            quarter_turns: 0,
+            adjusted: None,
+            adjusting: None,
+            textures: Vec::new(),
+            mouse_pos: Vector2::zero(),
+        };
+        picture.start_loading(bounding_rect.is_none());
+        picture
+    }
+
+    // Decodes the linked file on a worker thread, the current image (if any) is shown until it's done.
+    fn start_loading(&mut self, fit_to_image: bool) {
+        let Some(link) = &mut self.link else {
+            return;
+        };
+        // taken before reading, so that changes made while decoding cause another reload
+        link.modified = std::fs::metadata(&link.path).and_then(|metadata| metadata.modified()).ok();
+        link.last_check = Instant::now();
+
+        let (sender, receiver) = mpsc::channel();
+        let path = link.path.clone();
+        std::thread::spawn(move || {
+            // the placeholder might have been deleted in the meantime, then nobody is interested in the result
+            let _ = sender.send(decode_image_file(&path));
+        });
+        self.loading = Some(ImageLoading { receiver, start_time: Instant::now(), fit_to_image });
+    }
+
+    // `start_time` is `None` if the linked file is missing, then the placeholder is crossed out.
+    fn draw_placeholder<'a>(&self, painter: &mut WorldPainter<'a, EguiPainter>, start_time: Option<Instant>, camera: &Camera) {
+        let gray = Color { red: 128, green: 128, blue: 128, alpha: 255 };
+        let color = if start_time.is_some() { gray } else { Color::from_rgb(200, 60, 60) };
+        painter.draw_polygon_filled(&self.bounding_rect.vertices(), &Fill::Solid(Color { alpha: 40, ..gray }), self.bounding_rect, camera);
+        let mut outline = Stroke::new(color, camera.size_to_world_coordinates(Number::<ScreenSpace>::new(1.0)));
+        outline.dash = DashPattern::Dashed;
+        painter.draw_rectangle(self.bounding_rect, outline, camera);
+
+        let Some(start_time) = start_time else {
+            let rect = self.bounding_rect;
+            outline.dash = DashPattern::Solid;
+            painter.draw_line(rect.p1, rect.p2, outline, camera);
+            painter.draw_line(Vector2::new(rect.p2.x, rect.p1.y), Vector2::new(rect.p1.x, rect.p2.y), outline, camera);
+            return;
+        };
+
+        // three quarters of a circle, going around once per second
+        let size_limit = self.bounding_rect.width().value.min(self.bounding_rect.height().value) * 0.25;
+        let radius = camera.size_to_world_coordinates(Self::SPINNER_RADIUS).value.min(size_limit);
+        let center = self.bounding_rect.center();
+        let rotation = start_time.elapsed().as_secs_f32() * std::f32::consts::TAU;
+        let segments = 24;
+        let arc = (0..=segments).map(|i| {
+            let angle = rotation + i as f32 / segments as f32 * std::f32::consts::TAU * 0.75;
+            center + Vector2::new(radius * angle.cos(), radius * angle.sin())
+        }).collect::<Vec<_>>();
+        painter.draw_polyline(&arc, Stroke::new(gray, Number::new(radius * 0.25)), camera);
+    }
+
+    pub fn load(reader: &mut SceneReader) -> Result<Self, Error> {
+        let bounding_rect = reader.rect()?;
+        let flip = Flip { horizontal: reader.bool()?, vertical: reader.bool()? };
+        let image_name = reader.text()?;
+        let image = reader.image()?;
+        let mut picture = Self { flip, ..Self::new(bounding_rect, image, image_name) };
+        // the first version with links wrote the path right after the image, without the keyword
+        if reader.peek().is_some_and(|token| token.starts_with('s')) {
+            picture.link = Some(Link::embedded(PathBuf::from(reader.text()?)));
+        }
+        picture.load_options(reader)?;
+        picture.current_frame = picture.current_frame.min(picture.frames.len().saturating_sub(1));
+        picture.start_adjusting();
+        Ok(picture)
+    }
+
+    // Reads the file again, if it's missing then the picture stays as a placeholder that can be relinked.
+    pub fn load_linked(reader: &mut SceneReader) -> Result<Self, Error> {
+        let bounding_rect: Rectangle<WorldSpace> = reader.rect()?;
+        let flip = Flip { horizontal: reader.bool()?, vertical: reader.bool()? };
+        let path = PathBuf::from(reader.text()?);
+        let mut picture = Self { flip, ..Self::load_in_background(path, bounding_rect.p1, Some(bounding_rect), true) };
+        picture.load_options(reader)?;
+        Ok(picture)
+    }
+
+    // The optional parts at the end of the line, each one starts with a keyword.
+    fn load_options(&mut self, reader: &mut SceneReader) -> Result<(), Error> {
+        while reader.has_more() {
+            match reader.keyword()? {
+                "path"   => self.link = Some(Link::embedded(PathBuf::from(reader.text()?))),
+                "adjust" => self.adjustments = reader.adjustments()?,
+                "rotate" => self.quarter_turns = (reader.count()? % 4) as u8,
+                "frames" => self.frames = self.load_frames(reader)?,
+                "frozen" => {
+                    self.current_frame = reader.count()?;
+                    self.is_playing = false;
+                },
+                keyword  => return Err(reader.error(&format!("unknown picture option \"{}\"", keyword))),
+            }
+        }
+        Ok(())
+    }
+
+    // The delays of all frames, followed by the images of the frames after the first one, which is `image`.
+    fn load_frames(&self, reader: &mut SceneReader) -> Result<Vec<AnimationFrame>, Error> {
+        let Some(first) = &self.image else {
+            return Err(reader.error("linked pictures can't have embedded frames"));
+        };
+        let count = reader.count()?;
+        let mut delays = Vec::with_capacity(count);
+        for _ in 0..count {
+            delays.push(Duration::from_secs_f32(reader.number()?.max(0.0) / 1000.0));
+        }
+        let mut frames = Vec::with_capacity(count);
+        for (i, delay) in delays.into_iter().enumerate() {
+            let image = if i == 0 { Arc::clone(first) } else { Arc::new(reader.image()?) };
+            frames.push(AnimationFrame { image, delay });
+        }
+        Ok(frames)
+    }
+
+    fn save_frames(&self, writer: &mut SceneWriter) {
+        if self.frames.is_empty() {
+            return;
+        }
+        writer.keyword("frames");
+        writer.count(self.frames.len());
+        for frame in self.frames.iter() {
+            writer.number(frame.delay.as_secs_f32() * 1000.0);
+        }
+        for frame in self.frames.iter().skip(1) {
+            writer.image(&frame.image);
+        }
+    }
+
+    fn save_options(&self, writer: &mut SceneWriter) {
+        if !self.adjustments.is_identity() {
+            writer.keyword("adjust");
+            writer.adjustments(self.adjustments);
+        }
+        // the image itself is saved the way it was loaded, so that linked files can be reloaded
+        if self.quarter_turns != 0 {
+            writer.keyword("rotate");
+            writer.count(self.quarter_turns as usize);
+        }
+        if !self.is_playing {
+            writer.keyword("frozen");
+            writer.count(self.current_frame);
+        }
+    }
+
+    // The frame that is shown, `None` while the picture is being decoded.
+    fn current_image(&self) -> Option<&Arc<image::DynamicImage>> {
+        self.frames.get(self.current_frame).map(|frame| &frame.image).or(self.image.as_ref())
+    }
+
+    fn frame_sources(&self) -> Vec<Arc<image::DynamicImage>> {
+        if self.frames.is_empty() {
+            self.image.iter().cloned().collect()
+        }
+        else {
+            self.frames.iter().map(|frame| Arc::clone(&frame.image)).collect()
+        }
+    }
+
+    fn frame_delay(&self) -> Duration {
+        let delay = self.frames.get(self.current_frame).map_or(Duration::ZERO, |frame| frame.delay);
+        if delay < Self::MINIMUM_FRAME_DELAY { Self::DEFAULT_FRAME_DELAY } else { delay }
+    }
+
+    fn set_adjusted(&mut self, adjusted: AdjustedImage) {
+        self.textures = adjusted.images.iter().map(|_| OnceCell::new()).collect();
+        self.adjusted = Some(adjusted);
+    }
+
+    fn adjusted_image_is_current(&self) -> bool {
+        match (&self.adjusted, &self.image) {
+            (Some(adjusted), Some(image)) => adjusted.adjustments == self.adjustments
+                                             && adjusted.quarter_turns == self.quarter_turns
+                                             && adjusted.images.len() == self.frames.len().max(1)
+                                             && Arc::ptr_eq(&adjusted.source, image),
+            _                             => false,
+        }
+    }
+
+    // Brings `adjusted` up to date with `image`, `adjustments` and `quarter_turns`.
+    // If a worker thread is already busy then this happens again when it's done, see `poll_adjusting`.
+    fn start_adjusting(&mut self) {
+        if self.adjusting.is_some() || self.adjusted_image_is_current() {
+            return;
+        }
+        let Some(image) = &self.image else {
+            return;
+        };
+        let source = Arc::clone(image);
+        if self.adjustments.is_identity() && self.quarter_turns == 0 {
+            self.set_adjusted(AdjustedImage { source, adjustments: self.adjustments, quarter_turns: 0, images: self.frame_sources() });
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let frames = self.frame_sources();
+        let adjustments = self.adjustments;
+        let quarter_turns = self.quarter_turns;
+        std::thread::spawn(move || {
+            let images = frames.iter().map(|frame| Arc::new(adjust_image(frame, adjustments, quarter_turns))).collect();
+            let _ = sender.send(AdjustedImage { source, adjustments, quarter_turns, images });
+        });
+        self.adjusting = Some(ImageAdjusting { receiver, start_time: Instant::now() });
+    }
+
+    fn poll_adjusting(&mut self) {
+        let Some(adjusting) = &self.adjusting else {
+            return;
+        };
+        match adjusting.receiver.try_recv() {
+            Ok(adjusted)                    => self.set_adjusted(adjusted),
+            Err(TryRecvError::Empty)        => return,
+            // the thread panicked, the previous version stays
+            Err(TryRecvError::Disconnected) => {},
+        }
+        self.adjusting = None;
+        // the adjustments or the image might have changed in the meantime
+        self.start_adjusting();
+    }
+
+    // OK(Some(picture)): the dropped file is a supported picture, it's decoded in the background
     // OK(None):          the dropped file is not a picture in a supported format
-    // Err(...):          the dropeed file is a supported picture but we could not read it because of some other reason
-    pub fn from_dropped_file(dropped_file: &egui::DroppedFile, top_left: Vector2<WorldSpace>) -> Result<Option<Self>, String> {
+    // Err(...):          we could not access the dropped file
+    pub fn from_dropped_file(dropped_file: &egui::DroppedFile, top_left: Vector2<WorldSpace>) -> Result<Option<Self>, Error> {
         let Some(file_path) = &dropped_file.path else {
             // This should never happen, `path` should only be `None` on the Wasm backend.
-            return Err(format!("Error accessing dropped file. "));
+            return Err(Error::MissingPath);
         };
+        Ok(Self::from_file(file_path, top_left))
+    }
 
+    // `None` if the file is not a picture in a supported format, otherwise it's decoded in the background.
+    pub fn from_file(file_path: &Path, top_left: Vector2<WorldSpace>) -> Option<Self> {
         let Some(file_extension) = file_path.extension() else {
             // We don't try to guess the format if the file doesn't have an extension,
             // just assume it isn't a supported image format.
-            return Ok(None);
+            return None;
         };
 
         let mut format_is_supported = false;
         for format in image::ImageFormat::all() {
             if format.can_read() {
                 for ext in format.extensions_str() {
-                    if *ext == file_extension.to_string_lossy() {
+                    if ext.eq_ignore_ascii_case(&file_extension.to_string_lossy()) {
                         format_is_supported = true;
                     }
                 }
             }
         }
         if !format_is_supported {
-            return Ok(None);
+            return None;
         }
 
-        let image = image::ImageReader::open(&file_path)
-                            .map_err(|err| err.to_string())?
-                            .decode()
-                            .map_err(|err| err.to_string())?;
+        Some(Picture::load_in_background(file_path.to_path_buf(), top_left, None, false))
+    }
 
-        Ok(Some(Picture {
-            base: PaintObjectCommon::default(),
-            bounding_rect: Rectangle::from_point_and_size(top_left, Number::new(image.width() as f32), Number::new(image.height() as f32)),
-            image,
-            image_name: file_path.to_string_lossy().into_owned(),
-            texture: OnceCell::new(),
-            mouse_pos: Vector2::zero(),
-        }))
+    // The image is shown at its original size.
+    pub fn from_pasted_image(image: image::DynamicImage, top_left: Vector2<WorldSpace>) -> Self {
+        let rect = Rectangle::from_point_and_size(top_left, Number::new(image.width() as f32), Number::new(image.height() as f32));
+        Self::new(rect, image, String::from("pasted picture"))
     }
 }
 
@@ -78,31 +418,254 @@ impl PaintObject<EguiPainter> for Picture {
     }
     
     fn draw<'a>(&self, painter: &mut WorldPainter<'a, EguiPainter>, camera: &Camera) {
-        let texture = self.texture.get_or_init(|| {
-            painter.load_image(&self.image_name, &self.image)
-        });
-        painter.draw_image(self.bounding_rect, texture, camera);
+        if let Some(adjusted) = &self.adjusted {
+            // after a reload there can be fewer adjusted frames until the new ones are ready
+            let index = self.current_frame.min(adjusted.images.len() - 1);
+            let texture = self.textures[index].get_or_init(|| {
+                painter.load_image(&self.image_name, &adjusted.images[index])
+            });
+            painter.draw_image(self.bounding_rect, texture, self.flip, camera);
+        }
+        else {
+            let start_time = self.loading.as_ref().map(|loading| loading.start_time)
+                                 .or(self.adjusting.as_ref().map(|adjusting| adjusting.start_time));
+            self.draw_placeholder(painter, start_time, camera);
+        }
+    }
+
+    // pictures that are still loading are left out, animations are exported as the frame that is shown
+    fn draw_for_export<'a>(&self, painter: &mut WorldPainter<'a, ExportPainter>, camera: &Camera) {
+        let Some(image) = self.current_image() else {
+            return;
+        };
+        // the export can't wait for the worker thread
+        let adjusted_image = match &self.adjusted {
+            Some(adjusted) if self.adjusted_image_is_current() => Arc::clone(&adjusted.images[self.current_frame.min(adjusted.images.len() - 1)]),
+            _                                                  => Arc::new(adjust_image(image, self.adjustments, self.quarter_turns)),
+        };
+        let texture = painter.load_image(&self.image_name, &adjusted_image);
+        painter.draw_image(self.bounding_rect, &texture, self.flip, camera);
     }
     
-    fn is_under_mouse(&self) -> bool {
-        self.get_bounding_rect().contains_point(self.mouse_pos)
+    fn is_under_mouse(&self, _camera: &Camera) -> bool {
+        self.bounding_rect.contains_point(self.mouse_pos)
     }
     
     fn get_bounding_rect(&self) -> Rectangle<WorldSpace> {
         self.bounding_rect
     }
 
+    fn color_at(&self, point: Vector2<WorldSpace>) -> Option<Color> {
+        if !self.bounding_rect.contains_point(point) || self.bounding_rect.width().value <= 0.0 || self.bounding_rect.height().value <= 0.0 {
+            return None;
+        }
+        let mut u = (point.x - self.bounding_rect.p1.x) / self.bounding_rect.width().value;
+        let mut v = (point.y - self.bounding_rect.p1.y) / self.bounding_rect.height().value;
+        if self.flip.horizontal {
+            u = 1.0 - u;
+        }
+        if self.flip.vertical {
+            v = 1.0 - v;
+        }
+        // each turn back moves the left side of the drawn picture to the bottom of the image
+        for _ in 0..self.quarter_turns {
+            (u, v) = (v, 1.0 - u);
+        }
+
+        let image = self.current_image()?;
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let x = ((u * width as f32) as u32).min(width - 1);
+        let y = ((v * height as f32) as u32).min(height - 1);
+        let [red, green, blue, alpha] = image.get_pixel(x, y).0;
+        Some(self.adjustments.apply_to(Color { red, green, blue, alpha }))
+    }
+
     fn shift_with(&mut self, p: Vector2<WorldSpace>) {
         self.bounding_rect.p1 += p;
         self.bounding_rect.p2 += p;
     }
 
     fn resize_to(&mut self, new_size: Rectangle<WorldSpace>) {
-        self.bounding_rect = new_size;
+        if new_size.p1.x > new_size.p2.x {
+            self.flip.horizontal = !self.flip.horizontal;
+        }
+        if new_size.p1.y > new_size.p2.y {
+            self.flip.vertical = !self.flip.vertical;
+        }
+        self.bounding_rect = Rectangle::from_points_well_ordered(new_size.p1, new_size.p2);
+    }
+
+    // see `can_be_clipped`
+    fn clip_to(&mut self, _new_size: Rectangle<WorldSpace>) {}
+
+    // Cropping would have to go through the adjusted images and all the texture levels, so pictures are left as they are.
+    fn can_be_clipped(&self) -> bool {
+        false
+    }
+
+    fn save(&self, writer: &mut SceneWriter) {
+        if let Some(link) = self.link.as_ref().filter(|link| link.is_linked) {
+            writer.begin_object(Self::LINKED_KIND);
+            writer.rect(self.bounding_rect);
+            writer.bool(self.flip.horizontal);
+            writer.bool(self.flip.vertical);
+            writer.text(&link.path.to_string_lossy());
+            self.save_options(writer);
+            return;
+        }
+
+        // there is nothing to save until the image is decoded
+        let Some(image) = &self.image else {
+            return;
+        };
+        writer.begin_object(Self::KIND);
+        writer.rect(self.bounding_rect);
+        writer.bool(self.flip.horizontal);
+        writer.bool(self.flip.vertical);
+        writer.text(&self.image_name);
+        writer.image(image);
+        // so that the picture can be linked again later
+        if let Some(link) = &self.link {
+            writer.keyword("path");
+            writer.text(&link.path.to_string_lossy());
+        }
+        self.save_frames(writer);
+        self.save_options(writer);
+    }
+
+    fn is_loading(&self) -> bool {
+        self.loading.is_some() || self.adjusting.is_some()
+    }
+
+    fn poll_loading(&mut self) -> Option<Result<(), Error>> {
+        self.poll_adjusting();
+        let loading = self.loading.as_ref()?;
+        let result = match loading.receiver.try_recv() {
+            Ok(result)                      => result,
+            Err(TryRecvError::Empty)        => return None,
+            // the thread panicked
+            Err(TryRecvError::Disconnected) => Err(Error::Decode { path: Some(PathBuf::from(&self.image_name)), source: None }),
+        };
+        let fit_to_image = loading.fit_to_image;
+        self.loading = None;
+
+        let mut frames = match result {
+            Ok(frames) => frames,
+            Err(err)   => return Some(Err(err)),
+        };
+        let image = Arc::clone(&frames[0].image);
+        if fit_to_image {
+            let (width, height) = if self.quarter_turns.is_multiple_of(2) { image.dimensions() } else { (image.height(), image.width()) };
+            self.bounding_rect = Rectangle::from_point_and_size(self.bounding_rect.p1, Number::new(width as f32), Number::new(height as f32));
+        }
+        if frames.len() == 1 {
+            frames.clear();
+        }
+        self.image = Some(image);
+        // a reloaded file can have fewer frames
+        self.frames = frames;
+        self.current_frame = self.current_frame.min(self.frames.len().saturating_sub(1));
+        // after a reload the previous version is shown until the new one is adjusted
+        self.start_adjusting();
+        Some(Ok(()))
+    }
+
+    fn adjustments(&self) -> Option<ImageAdjustments> {
+        Some(self.adjustments)
+    }
+
+    fn set_adjustments(&mut self, adjustments: ImageAdjustments) {
+        self.adjustments = adjustments;
+        self.start_adjusting();
+    }
+
+    fn animate(&mut self) -> Option<Duration> {
+        if !self.is_playing || self.frames.len() < 2 {
+            return None;
+        }
+        let elapsed = self.frame_start.elapsed();
+        let delay = self.frame_delay();
+        if elapsed >= delay {
+            self.current_frame = (self.current_frame + 1) % self.frames.len();
+            // a late frame shortens the next one, but after a long stall the animation doesn't race to catch up
+            self.frame_start = if elapsed < delay * 2 { self.frame_start + delay } else { Instant::now() };
+        }
+        Some(self.frame_delay().saturating_sub(self.frame_start.elapsed()))
     }
 
-    fn clip_to(&mut self, new_size: Rectangle<WorldSpace>) {
-        todo!()
+    fn playback(&self) -> Option<Playback> {
+        if self.frames.len() < 2 {
+            return None;
+        }
+        Some(Playback { is_playing: self.is_playing, frame: self.current_frame, frame_count: self.frames.len() })
+    }
+
+    fn set_playback(&mut self, playback: Playback) {
+        let frame = playback.frame.min(self.frames.len().saturating_sub(1));
+        if frame != self.current_frame || (playback.is_playing && !self.is_playing) {
+            self.frame_start = Instant::now();
+        }
+        self.current_frame = frame;
+        self.is_playing = playback.is_playing;
+    }
+
+    fn rotate_quarter_turn(&mut self, rotation: Rotation) -> bool {
+        // the rotation is applied before the flip, and a mirrored picture turns the other way
+        let is_mirrored = self.flip.horizontal != self.flip.vertical;
+        let turns = if (rotation == Rotation::Clockwise) != is_mirrored { 1 } else { 3 };
+        self.quarter_turns = (self.quarter_turns + turns) % 4;
+
+        let center = self.bounding_rect.center();
+        let half_size = Vector2::new(self.bounding_rect.height().value, self.bounding_rect.width().value) * 0.5;
+        self.bounding_rect = Rectangle { p1: center - half_size, p2: center + half_size };
+        self.start_adjusting();
+        true
+    }
+
+    fn file_link(&self) -> Option<FileLink> {
+        self.link.as_ref().map(|link| FileLink {
+            path: link.path.clone(),
+            is_linked: link.is_linked,
+            is_missing: self.image.is_none() && self.loading.is_none(),
+        })
+    }
+
+    fn set_file_link(&mut self, new_link: FileLink) {
+        let Some(link) = &mut self.link else {
+            return;
+        };
+        // there is nothing to embed without an image
+        if new_link.is_linked || self.image.is_some() {
+            link.is_linked = new_link.is_linked;
+        }
+        if link.path != new_link.path {
+            link.path = new_link.path;
+            self.image_name = link.path.to_string_lossy().into_owned();
+            self.start_loading(false);
+        }
+    }
+
+    fn watch_file(&mut self) -> bool {
+        let Some(link) = self.link.as_mut().filter(|link| link.is_linked) else {
+            return false;
+        };
+        if self.loading.is_some() || link.last_check.elapsed() < FILE_WATCH_INTERVAL {
+            return true;
+        }
+        link.last_check = Instant::now();
+        // a file that disappeared is not reloaded, the last version of the picture stays until it's back
+        let modified = std::fs::metadata(&link.path).and_then(|metadata| metadata.modified()).ok();
+        if modified.is_some() && modified != link.modified {
+            self.start_loading(false);
+        }
+        true
+    }
+
+    fn keeps_aspect_ratio(&self) -> bool {
+        true
     }
 }
 
@@ -124,19 +687,12 @@ impl Default for PictureTool {
 }
 
 impl Tool<EguiPainter, egui::ImageSource<'static>> for PictureTool {
-    fn update(&mut self, input: &UserInput, _stroke: Stroke<WorldSpace>, camera: &Camera) -> Result<Option<Box<dyn PaintObject<EguiPainter>>>, String> {
+    fn update(&mut self, input: &UserInput, _stroke: Stroke<WorldSpace>, _fill: &Fill, camera: &Camera) -> Result<Option<Box<dyn PaintObject<EguiPainter>>>, Error> {
         match input {
             UserInput::MouseClick { position, .. } => {
-                if let Some((image, image_name)) = image_from_open_file_dialog()? {
+                if let Some(path) = path_from_open_file_dialog() {
                     let pos = camera.point_to_world_coordinates(*position);
-                    return Ok(Some(Box::new(Picture {
-                        base: PaintObjectCommon::default(),
-                        bounding_rect: Rectangle::from_point_and_size(pos, Number::new(image.width() as f32), Number::new(image.height() as f32)),
-                        image,
-                        image_name,
-                        texture: OnceCell::new(),
-                        mouse_pos: pos,
-                    })));
+                    return Ok(Some(Box::new(Picture::load_in_background(path, pos, None, false))));
                 }
             },
             UserInput::MouseMove { button: MouseButton::Left, position, .. } => {
@@ -151,22 +707,15 @@ impl Tool<EguiPainter, egui::ImageSource<'static>> for PictureTool {
                 if let (Some(p1), Some(p2)) = (self.p1, self.p2) {
                     self.p1 = None;
                     self.p2 = None;
-                    if let Some((image, image_name)) = image_from_open_file_dialog()? {
-                        return Ok(Some(Box::new(Picture {
-                            base: PaintObjectCommon::default(),
-                            bounding_rect: Rectangle { p1, p2 },
-                            image,
-                            image_name,
-                            texture: OnceCell::new(),
-                            mouse_pos: p2,
-                        })));
+                    if let Some(path) = path_from_open_file_dialog() {
+                        return Ok(Some(Box::new(Picture::load_in_background(path, p1, Some(Rectangle { p1, p2 }), false))));
                     }
                 }
             },
             _ => {},
         }
 
-        return Ok(None);
+        Ok(None)
     }
     
     fn draw<'a>(&self, painter: &mut WorldPainter<'a, EguiPainter>, bg_color: Color, camera: &Camera) {
@@ -185,7 +734,29 @@ impl Tool<EguiPainter, egui::ImageSource<'static>> for PictureTool {
     }
 }
 
-fn image_from_open_file_dialog() -> Result<Option<(image::DynamicImage, String)>, String> {
+// Rotating keeps every pixel, but adjusted images always get 8 bits per channel, which is plenty for looking at them.
+fn adjust_image(image: &image::DynamicImage, adjustments: ImageAdjustments, quarter_turns: u8) -> image::DynamicImage {
+    let rotated = match quarter_turns {
+        1 => image.rotate90(),
+        2 => image.rotate180(),
+        3 => image.rotate270(),
+        _ => image.clone(),
+    };
+    if adjustments.is_identity() {
+        return rotated;
+    }
+
+    let mut result = rotated.into_rgba8();
+    for pixel in result.pixels_mut() {
+        let [red, green, blue, alpha] = pixel.0;
+        let color = adjustments.apply_to(Color { red, green, blue, alpha });
+        pixel.0 = [color.red, color.green, color.blue, color.alpha];
+    }
+    image::DynamicImage::ImageRgba8(result)
+}
+
+
+pub fn path_from_open_file_dialog() -> Option<PathBuf> {
     let mut extensions = Vec::new();
     for image_format in image::ImageFormat::all() {
         if image_format.can_read() {
@@ -194,15 +765,65 @@ fn image_from_open_file_dialog() -> Result<Option<(image::DynamicImage, String)>
     }
     let filter_name = extensions.iter().map(|ext| format!("*.{ext}")).collect::<Vec<String>>().join(", ");
     let dialog = FileDialog::new().add_filter(filter_name, &extensions);
-    
-    if let Some(file_path) = dialog.pick_file() {
-        let image = image::ImageReader::open(&file_path)
-                        .map_err(|err| err.to_string())?
-                        .decode()
-                        .map_err(|err| err.to_string())?;
-        Ok(Some((image, file_path.to_string_lossy().into_owned())))
-    }
-    else {
-        Ok(None)
+    dialog.pick_file()
+}
+
+
+// Animated GIF, PNG and WebP files are decoded with all of their frames, everything else has just one frame.
+fn decode_image_file(path: &Path) -> Result<Vec<AnimationFrame>, Error> {
+    if let Some(frames) = decode_animation(path)? {
+        return Ok(frames);
     }
+    let image = decode_still_image(path)?;
+    Ok(vec![AnimationFrame { image: Arc::new(image), delay: Duration::ZERO }])
+}
+
+
+// `None` if the file isn't an animation, i.e. if it's in a format without animations or has only one frame.
+fn decode_animation(path: &Path) -> Result<Option<Vec<AnimationFrame>>, Error> {
+    let open = || std::fs::File::open(path).map(std::io::BufReader::new).map_err(|err| Error::io(path, err));
+    let to_error = |err| Error::image(Some(path), err);
+    let frames = match image::ImageFormat::from_path(path) {
+        Ok(image::ImageFormat::Gif) => image::codecs::gif::GifDecoder::new(open()?).map_err(to_error)?.into_frames(),
+        Ok(image::ImageFormat::Png) => {
+            let decoder = image::codecs::png::PngDecoder::new(open()?).map_err(to_error)?;
+            if !decoder.is_apng().map_err(to_error)? {
+                return Ok(None);
+            }
+            decoder.apng().map_err(to_error)?.into_frames()
+        },
+        Ok(image::ImageFormat::WebP) => {
+            let decoder = image::codecs::webp::WebPDecoder::new(open()?).map_err(to_error)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        },
+        _ => return Ok(None),
+    };
+    let frames = frames.collect_frames().map_err(to_error)?;
+    if frames.len() < 2 {
+        return Ok(None);
+    }
+    Ok(Some(frames.into_iter().map(|frame| {
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        AnimationFrame {
+            delay: Duration::from_secs_f64(numerator as f64 / denominator.max(1) as f64 / 1000.0),
+            image: Arc::new(image::DynamicImage::ImageRgba8(frame.into_buffer())),
+        }
+    }).collect()))
+}
+
+
+// Photos are often stored sideways together with an EXIF orientation, the image is turned upright here.
+fn decode_still_image(path: &Path) -> Result<image::DynamicImage, Error> {
+    let mut decoder = image::ImageReader::open(path)
+        .map_err(|err| Error::io(path, err))?
+        .into_decoder()
+        .map_err(|err| Error::image(Some(path), err))?;
+    // a broken orientation tag is not worth failing over
+    let orientation = decoder.orientation().unwrap_or(image::metadata::Orientation::NoTransforms);
+    let mut image = image::DynamicImage::from_decoder(decoder).map_err(|err| Error::image(Some(path), err))?;
+    image.apply_orientation(orientation);
+    Ok(image)
 }
@@ -0,0 +1,388 @@
+use crate::egui_painter::EguiPainter;
+use crate::primitives::*;
+use crate::engine::*;
+use crate::error::Error;
+use crate::scene_file::{SceneWriter, SceneReader};
+use eframe::egui;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShapeKind {
+    Rectangle,
+    Ellipse,
+    Polygon,
+}
+
+
+// A closed shape that can be filled.
+pub struct Shape {
+    base: PaintObjectCommon,
+    kind: ShapeKind,
+    bounding_rect: Rectangle<WorldSpace>, // always well ordered, rectangles and ellipses fill it exactly
+    points: Vec<Vector2<WorldSpace>>, // only used by polygons
+    stroke: Stroke<WorldSpace>,
+    fill: Fill,
+    mouse_pos: Vector2<WorldSpace>,
+}
+
+impl Shape {
+    pub const KIND: &str = "shape";
+    // used where the outline of an ellipse is needed independently of the zoom, e.g. for hit testing
+    const ELLIPSE_SEGMENTS: usize = 64;
+
+    fn new(kind: ShapeKind, bounding_rect: Rectangle<WorldSpace>, points: Vec<Vector2<WorldSpace>>, stroke: Stroke<WorldSpace>, fill: Fill) -> Self {
+        Self {
+            base: PaintObjectCommon::default(),
+            kind,
+            bounding_rect: Rectangle::from_points_well_ordered(bounding_rect.p1, bounding_rect.p2),
+            points,
+            stroke,
+            fill,
+            mouse_pos: Vector2::zero(),
+        }
+    }
+
+    fn polygon(points: Vec<Vector2<WorldSpace>>, stroke: Stroke<WorldSpace>, fill: Fill) -> Self {
+        let bounding_rect = bounding_rect_of(&points);
+        Self::new(ShapeKind::Polygon, bounding_rect, points, stroke, fill)
+    }
+
+    pub fn load(reader: &mut SceneReader) -> Result<Self, Error> {
+        let kind = match reader.count()? {
+            0 => ShapeKind::Rectangle,
+            1 => ShapeKind::Ellipse,
+            2 => ShapeKind::Polygon,
+            n => return Err(reader.error(&format!("unknown kind of shape {}", n))),
+        };
+        let bounding_rect = reader.rect()?;
+        let stroke = reader.stroke()?;
+        let fill = reader.fill()?;
+        let point_count = reader.count()?;
+        let mut points = Vec::new();
+        for _ in 0..point_count {
+            points.push(reader.point()?);
+        }
+        if kind == ShapeKind::Polygon && points.len() < 3 {
+            return Err(reader.error("a polygon needs at least 3 points"));
+        }
+        Ok(Self::new(kind, bounding_rect, points, stroke, fill))
+    }
+
+    // Shared by drawing on the screen and exporting.
+    fn draw_with<P: ScreenPainter + ?Sized>(&self, painter: &mut WorldPainter<'_, P>, camera: &Camera) {
+        // more segments for bigger ellipses, so that they stay smooth when zooming in
+        let radius = camera.size_to_screen_coordinates(Number::new(self.bounding_rect.width().value.max(self.bounding_rect.height().value) * 0.5));
+        let segments = ((radius.value.max(0.0).sqrt() * 4.0) as usize).clamp(16, 256);
+        let mut outline = self.outline(segments);
+        if outline.is_empty() {
+            return;
+        }
+
+        painter.draw_polygon_filled(&outline, &self.fill, self.bounding_rect, camera);
+        outline.push(outline[0]);
+        painter.draw_polyline(&outline, self.stroke, camera);
+    }
+
+    fn outline(&self, ellipse_segments: usize) -> Vec<Vector2<WorldSpace>> {
+        match self.kind {
+            ShapeKind::Rectangle => self.bounding_rect.vertices().to_vec(),
+            ShapeKind::Ellipse   => {
+                let center = self.bounding_rect.center();
+                let rx = self.bounding_rect.width().value * 0.5;
+                let ry = self.bounding_rect.height().value * 0.5;
+                (0..ellipse_segments).map(|i| {
+                    let angle = i as f32 / ellipse_segments as f32 * std::f32::consts::TAU;
+                    center + Vector2::new(rx * angle.cos(), ry * angle.sin())
+                }).collect()
+            },
+            ShapeKind::Polygon   => self.points.clone(),
+        }
+    }
+
+    fn distance_to_outline(outline: &[Vector2<WorldSpace>], point: Vector2<WorldSpace>) -> f32 {
+        (0..outline.len())
+            .map(|i| point.distance_to_segment(outline[i], outline[(i + 1) % outline.len()]))
+            .fold(f32::INFINITY, f32::min)
+    }
+}
+
+impl PaintObject<EguiPainter> for Shape {
+    fn base(&self) -> &PaintObjectCommon {
+        &self.base
+    }
+
+    fn base_mut(&mut self) -> &mut PaintObjectCommon {
+        &mut self.base
+    }
+
+    fn update(&mut self, input: &UserInput, camera: &Camera) {
+        if let Some(position) = input.mouse_position() {
+            self.mouse_pos = camera.point_to_world_coordinates(position);
+        }
+    }
+
+    fn draw<'a>(&self, painter: &mut WorldPainter<'a, EguiPainter>, camera: &Camera) {
+        self.draw_with(painter, camera);
+    }
+
+    fn draw_for_export<'a>(&self, painter: &mut WorldPainter<'a, ExportPainter>, camera: &Camera) {
+        self.draw_with(painter, camera);
+    }
+
+    fn is_under_mouse(&self, camera: &Camera) -> bool {
+        let tolerance = camera.size_to_world_coordinates(HIT_TOLERANCE) + self.stroke.thickness * 0.5;
+        if !self.bounding_rect.expanded_by(tolerance).contains_point(self.mouse_pos) {
+            return false;
+        }
+        let outline = self.outline(Self::ELLIPSE_SEGMENTS);
+        let is_inside = self.fill != Fill::None && polygon_contains_point(&outline, self.mouse_pos);
+        is_inside || Self::distance_to_outline(&outline, self.mouse_pos) < tolerance.value
+    }
+
+    fn get_bounding_rect(&self) -> Rectangle<WorldSpace> {
+        self.bounding_rect
+    }
+
+    fn color_at(&self, point: Vector2<WorldSpace>) -> Option<Color> {
+        let mut outline = self.outline(Self::ELLIPSE_SEGMENTS);
+        let fill_color = if polygon_contains_point(&outline, point) {
+            self.fill.color_at(self.bounding_rect.point_to_unit(point))
+        }
+        else {
+            None
+        };
+        // closed the same way as when drawing it
+        if let Some(first) = outline.first().copied() {
+            outline.push(first);
+        }
+        if stroke_contains_point(&outline, self.stroke, point) {
+            let transparent = Color { red: 0, green: 0, blue: 0, alpha: 0 };
+            return Some(self.stroke.color.over(fill_color.unwrap_or(transparent)));
+        }
+        fill_color
+    }
+
+    fn shift_with(&mut self, p: Vector2<WorldSpace>) {
+        self.bounding_rect.p1 += p;
+        self.bounding_rect.p2 += p;
+        for point in self.points.iter_mut() {
+            *point += p;
+        }
+    }
+
+    fn resize_to(&mut self, new_size: Rectangle<WorldSpace>) {
+        let old_size = self.bounding_rect;
+        for point in self.points.iter_mut() {
+            *point = old_size.map_point_to(*point, &new_size);
+        }
+        self.fill.mirror(Flip {
+            horizontal: new_size.p1.x > new_size.p2.x,
+            vertical: new_size.p1.y > new_size.p2.y,
+        });
+        self.bounding_rect = Rectangle::from_points_well_ordered(new_size.p1, new_size.p2);
+    }
+
+    fn clip_to(&mut self, new_size: Rectangle<WorldSpace>) {
+        // anything but a rectangle inside a rectangle stops being a rectangle or an ellipse when it's clipped
+        let clip_rect = Rectangle::from_points_well_ordered(new_size.p1, new_size.p2);
+        let clipped = clip_rect.clip_polygon(&self.outline(Self::ELLIPSE_SEGMENTS));
+        if clipped.is_empty() {
+            return;
+        }
+        // keep the gradient where it was on the canvas, even though the bounding rectangle changes
+        let old_size = self.bounding_rect;
+        let new_bounding_rect = bounding_rect_of(&clipped);
+        if let Some(gradient) = self.fill.gradient_mut() {
+            gradient.start = new_bounding_rect.point_to_unit(old_size.point_from_unit(gradient.start));
+            gradient.end   = new_bounding_rect.point_to_unit(old_size.point_from_unit(gradient.end));
+        }
+        *self = Self {
+            base: std::mem::take(&mut self.base),
+            mouse_pos: self.mouse_pos,
+            ..Self::polygon(clipped, self.stroke, self.fill.clone())
+        };
+    }
+
+    fn save(&self, writer: &mut SceneWriter) {
+        writer.begin_object(Self::KIND);
+        writer.count(match self.kind {
+            ShapeKind::Rectangle => 0,
+            ShapeKind::Ellipse   => 1,
+            ShapeKind::Polygon   => 2,
+        });
+        writer.rect(self.bounding_rect);
+        writer.stroke(self.stroke);
+        writer.fill(&self.fill);
+        writer.count(self.points.len());
+        for point in self.points.iter() {
+            writer.point(*point);
+        }
+    }
+
+    fn stroke(&self) -> Option<Stroke<WorldSpace>> {
+        Some(self.stroke)
+    }
+
+    fn set_stroke(&mut self, stroke: Stroke<WorldSpace>) {
+        self.stroke = stroke;
+    }
+
+    fn fill(&self) -> Option<Fill> {
+        Some(self.fill.clone())
+    }
+
+    fn set_fill(&mut self, fill: Fill) {
+        self.fill = fill;
+    }
+
+    fn snap_points(&self) -> Vec<Vector2<WorldSpace>> {
+        match self.kind {
+            ShapeKind::Rectangle => {
+                let mut points = self.bounding_rect.vertices().to_vec();
+                points.push(self.bounding_rect.center());
+                points
+            },
+            // the extreme points of the ellipse, the corners of its bounding box are not on it
+            ShapeKind::Ellipse   => {
+                let mut points = self.outline(4);
+                points.push(self.bounding_rect.center());
+                points
+            },
+            ShapeKind::Polygon   => {
+                let mut points = self.points.clone();
+                points.push(self.bounding_rect.center());
+                points
+            },
+        }
+    }
+}
+
+
+fn bounding_rect_of(points: &[Vector2<WorldSpace>]) -> Rectangle<WorldSpace> {
+    let Some(first) = points.first() else {
+        return Rectangle { p1: Vector2::zero(), p2: Vector2::zero() };
+    };
+    points.iter().fold(Rectangle { p1: *first, p2: *first }, |rect, p| Rectangle {
+        p1: Vector2::new(rect.p1.x.min(p.x), rect.p1.y.min(p.y)),
+        p2: Vector2::new(rect.p2.x.max(p.x), rect.p2.y.max(p.y)),
+    })
+}
+
+
+// Rectangles and ellipses are drawn by clicking two opposite corners,
+// polygons by clicking their vertices and then the first one again (or right clicking) to close them.
+pub struct ShapeTool {
+    kind: ShapeKind,
+    points: Vec<Vector2<WorldSpace>>,
+    stroke: Option<Stroke<WorldSpace>>, // Only optional because Stroke doesn't have a default value, so we have to wait until the first call to `update` to set it.
+    fill: Fill,
+    mouse_pos: Vector2<WorldSpace>,
+    icon: egui::ImageSource<'static>,
+}
+
+impl ShapeTool {
+    pub fn new(kind: ShapeKind) -> Self {
+        Self {
+            kind,
+            points: Vec::new(),
+            stroke: None,
+            fill: Fill::None,
+            mouse_pos: Vector2::zero(),
+            icon: match kind {
+                ShapeKind::Rectangle => egui::include_image!("../../img/rectangle_tool.png"),
+                ShapeKind::Ellipse   => egui::include_image!("../../img/ellipse_tool.png"),
+                ShapeKind::Polygon   => egui::include_image!("../../img/polygon_tool.png"),
+            },
+        }
+    }
+
+    // the shape that would be created if the user finished it now
+    fn preview(&self) -> Option<Shape> {
+        let stroke = self.stroke?;
+        match self.kind {
+            ShapeKind::Rectangle | ShapeKind::Ellipse => {
+                let start = *self.points.first()?;
+                Some(Shape::new(self.kind, Rectangle { p1: start, p2: self.mouse_pos }, Vec::new(), stroke, self.fill.clone()))
+            },
+            ShapeKind::Polygon => {
+                let mut points = self.points.clone();
+                points.push(self.mouse_pos);
+                (points.len() >= 3).then(|| Shape::polygon(points, stroke, self.fill.clone()))
+            },
+        }
+    }
+}
+
+impl Tool<EguiPainter, egui::ImageSource<'static>> for ShapeTool {
+    fn update(&mut self, input: &UserInput, stroke: Stroke<WorldSpace>, fill: &Fill, camera: &Camera) -> Result<Option<Box<dyn PaintObject<EguiPainter>>>, Error> {
+        self.stroke = Some(stroke);
+        self.fill = fill.clone();
+
+        match input {
+            UserInput::MouseMove { position, .. } => {
+                self.mouse_pos = camera.point_to_world_coordinates(*position);
+            },
+            UserInput::MouseClick { position, button: MouseButton::Left, is_shift_down: false } => {
+                let p = camera.point_to_world_coordinates(*position);
+                self.mouse_pos = p;
+                match self.kind {
+                    ShapeKind::Rectangle | ShapeKind::Ellipse => {
+                        if let Some(start) = self.points.pop() {
+                            if start != p {
+                                let shape = Shape::new(self.kind, Rectangle { p1: start, p2: p }, Vec::new(), stroke, fill.clone());
+                                return Ok(Some(Box::new(shape)));
+                            }
+                        }
+                        else {
+                            self.points.push(p);
+                        }
+                    },
+                    ShapeKind::Polygon => {
+                        let tolerance = camera.size_to_world_coordinates(HIT_TOLERANCE).value;
+                        let closes_polygon = self.points.len() >= 3 && self.points.first().is_some_and(|first| (*first - p).length() < tolerance);
+                        if closes_polygon {
+                            let points = std::mem::take(&mut self.points);
+                            return Ok(Some(Box::new(Shape::polygon(points, stroke, fill.clone()))));
+                        }
+                        if self.points.last().is_none_or(|last| (*last - p).length() >= tolerance) {
+                            self.points.push(p);
+                        }
+                    },
+                }
+            },
+            UserInput::MouseClick { button: MouseButton::Right, .. } if self.kind == ShapeKind::Polygon => {
+                let points = std::mem::take(&mut self.points);
+                if points.len() >= 3 {
+                    return Ok(Some(Box::new(Shape::polygon(points, stroke, fill.clone()))));
+                }
+            },
+            _ => {
+                // do nothing
+            },
+        }
+
+        Ok(None)
+    }
+
+    fn draw<'a>(&self, painter: &mut WorldPainter<'a, EguiPainter>, _bg_color: Color, camera: &Camera) {
+        if let Some(shape) = self.preview() {
+            shape.draw(painter, camera);
+        }
+        else if let (Some(stroke), Some(start)) = (self.stroke, self.points.first()) {
+            // a polygon with less than three vertices is only a line
+            painter.draw_line(*start, self.mouse_pos, stroke, camera);
+        }
+    }
+
+    fn display_name(&self) -> &str {
+        match self.kind {
+            ShapeKind::Rectangle => "rectangle",
+            ShapeKind::Ellipse   => "ellipse",
+            ShapeKind::Polygon   => "polygon",
+        }
+    }
+
+    fn icon(&self) -> egui::ImageSource<'static> {
+        self.icon.clone()
+    }
+}
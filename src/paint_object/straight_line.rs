@@ -1,6 +1,8 @@
 use crate::egui_painter::EguiPainter;
 use crate::primitives::*;
 use crate::engine::*;
+use crate::error::Error;
+use crate::scene_file::{SceneWriter, SceneReader};
 use eframe::egui;
 
 
@@ -12,6 +14,20 @@ pub struct StraightLine {
     mouse_pos: Vector2<WorldSpace>,
 }
 
+impl StraightLine {
+    pub const KIND: &str = "line";
+
+    pub fn load(reader: &mut SceneReader) -> Result<Self, Error> {
+        Ok(Self {
+            base: PaintObjectCommon::default(),
+            start: reader.point()?,
+            end: reader.point()?,
+            stroke: reader.stroke()?,
+            mouse_pos: Vector2::zero(),
+        })
+    }
+}
+
 impl PaintObject<EguiPainter> for StraightLine {
     fn base(&self) -> &PaintObjectCommon {
         &self.base
@@ -30,24 +46,33 @@ impl PaintObject<EguiPainter> for StraightLine {
     fn draw<'a>(&self, painter: &mut WorldPainter<'a, EguiPainter>, camera: &Camera) {
         painter.draw_line(self.start, self.end, self.stroke, camera);
     }
+
+    fn draw_for_export<'a>(&self, painter: &mut WorldPainter<'a, ExportPainter>, camera: &Camera) {
+        painter.draw_line(self.start, self.end, self.stroke, camera);
+    }
     
-    fn is_under_mouse(&self) -> bool {
-        let epsilon = 10.0;
-        (self.start - self.mouse_pos).length() + (self.end - self.mouse_pos).length() < (self.end - self.start).length() + epsilon
+    fn is_under_mouse(&self, camera: &Camera) -> bool {
+        let tolerance = camera.size_to_world_coordinates(HIT_TOLERANCE) + self.stroke.thickness * 0.5;
+        self.mouse_pos.distance_to_segment(self.start, self.end) < tolerance.value
     }
     
     fn get_bounding_rect(&self) -> Rectangle<WorldSpace> {
         Rectangle::from_points_well_ordered(self.start, self.end)
     }
 
+    fn color_at(&self, point: Vector2<WorldSpace>) -> Option<Color> {
+        stroke_contains_point(&[self.start, self.end], self.stroke, point).then_some(self.stroke.color)
+    }
+
     fn shift_with(&mut self, p: Vector2<WorldSpace>) {
-        self.start = self.start + p;
-        self.end   = self.end + p;
+        self.start += p;
+        self.end   += p;
     }
 
     fn resize_to(&mut self, new_size: Rectangle<WorldSpace>) {
-        self.start = new_size.p1;
-        self.end = new_size.p2;
+        let old_size = self.get_bounding_rect();
+        self.start = old_size.map_point_to(self.start, &new_size);
+        self.end   = old_size.map_point_to(self.end, &new_size);
     }
 
     fn clip_to(&mut self, new_size: Rectangle<WorldSpace>) {
@@ -70,6 +95,25 @@ impl PaintObject<EguiPainter> for StraightLine {
             self.end = p;
         }
     }
+
+    fn save(&self, writer: &mut SceneWriter) {
+        writer.begin_object(Self::KIND);
+        writer.point(self.start);
+        writer.point(self.end);
+        writer.stroke(self.stroke);
+    }
+
+    fn stroke(&self) -> Option<Stroke<WorldSpace>> {
+        Some(self.stroke)
+    }
+
+    fn set_stroke(&mut self, stroke: Stroke<WorldSpace>) {
+        self.stroke = stroke;
+    }
+
+    fn snap_points(&self) -> Vec<Vector2<WorldSpace>> {
+        vec![self.start, self.end, (self.start + self.end) * 0.5]
+    }
 }
 
 
@@ -92,7 +136,7 @@ impl Default for StraghtLineTool {
 }
 
 impl Tool<EguiPainter, egui::ImageSource<'static>> for StraghtLineTool {
-    fn update(&mut self, input: &UserInput, stroke: Stroke<WorldSpace>, camera: &Camera) -> Result<Option<Box<dyn PaintObject<EguiPainter>>>, String> {
+    fn update(&mut self, input: &UserInput, stroke: Stroke<WorldSpace>, _fill: &Fill, camera: &Camera) -> Result<Option<Box<dyn PaintObject<EguiPainter>>>, Error> {
         self.stroke = Some(stroke);
         
         match input {
@@ -122,7 +166,7 @@ impl Tool<EguiPainter, egui::ImageSource<'static>> for StraghtLineTool {
             },
         }
 
-        return Ok(None);
+        Ok(None)
     }
     
     fn draw<'a>(&self, painter: &mut WorldPainter<'a, EguiPainter>, _bg_color: Color, camera: &Camera) {
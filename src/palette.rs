@@ -0,0 +1,454 @@
+use std::path::{Path, PathBuf};
+use rfd::{FileDialog, MessageButtons, MessageDialog, MessageDialogResult, MessageLevel};
+use crate::primitives::*;
+use crate::storage;
+use crate::error::Error;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Swatch {
+    pub color: Color,
+    pub name: String,
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamedPalette {
+    pub name: String,
+    pub swatches: Vec<Swatch>,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteFormat {
+    // GIMP palette, also used by Inkscape and Krita. It has no alpha channel.
+    Gpl,
+    // one "#rrggbb" or "#rrggbbaa" color per line, optionally followed by a name,
+    // lines starting with ';' are comments and "; Name: ..." names the palette
+    HexList,
+}
+
+impl PaletteFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("gpl") => Self::Gpl,
+            _                                                          => Self::HexList,
+        }
+    }
+
+    pub fn parse(self, text: &str, default_name: &str) -> Result<NamedPalette, Error> {
+        match self {
+            Self::Gpl     => parse_gpl(text, default_name),
+            Self::HexList => parse_hex_list(text, default_name),
+        }
+    }
+
+    pub fn serialize(self, palette: &NamedPalette) -> String {
+        match self {
+            Self::Gpl     => serialize_gpl(palette),
+            Self::HexList => serialize_hex_list(palette),
+        }
+    }
+}
+
+
+fn parse_gpl(text: &str, default_name: &str) -> Result<NamedPalette, Error> {
+    let mut lines = text.trim_start_matches('\u{feff}').lines();
+    if lines.next().map(str::trim) != Some("GIMP Palette") {
+        return Err(invalid_palette(String::from("not a GIMP palette file, the \"GIMP Palette\" header is missing")));
+    }
+
+    let mut palette = NamedPalette { name: String::from(default_name), swatches: Vec::new() };
+    for (line_number, line) in lines.enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("Name:") {
+            palette.name = String::from(name.trim());
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let mut channel = || parts.next().and_then(|part| part.parse::<u8>().ok());
+        let (Some(red), Some(green), Some(blue)) = (channel(), channel(), channel()) else {
+            // the header is the first line, hence the + 2
+            return Err(invalid_palette(format!("invalid color on line {}: \"{}\"", line_number + 2, line)));
+        };
+        let name = parts.collect::<Vec<_>>().join(" ");
+        palette.swatches.push(Swatch { color: Color::from_rgb(red, green, blue), name });
+    }
+
+    Ok(palette)
+}
+
+
+// The alpha channel is dropped, `export_selected_with_file_dialog` asks first.
+fn serialize_gpl(palette: &NamedPalette) -> String {
+    let mut text = format!("GIMP Palette\nName: {}\nColumns: 8\n#\n", palette.name);
+    for swatch in palette.swatches.iter() {
+        let c = swatch.color;
+        text += &format!("{:3} {:3} {:3}\t{}\n", c.red, c.green, c.blue, swatch.name);
+    }
+    text
+}
+
+
+fn parse_hex_list(text: &str, default_name: &str) -> Result<NamedPalette, Error> {
+    let mut palette = NamedPalette { name: String::from(default_name), swatches: Vec::new() };
+    for (line_number, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix(';').and_then(|comment| comment.trim().strip_prefix("Name:")) {
+            palette.name = String::from(name.trim());
+            continue;
+        }
+        if line.is_empty() || line.starts_with(';') || line.starts_with("//") {
+            continue;
+        }
+        let (hex, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let Some(color) = Color::from_hex(hex) else {
+            return Err(invalid_palette(format!("invalid color on line {}: \"{}\"", line_number + 1, line)));
+        };
+        palette.swatches.push(Swatch { color, name: String::from(name.trim()) });
+    }
+    Ok(palette)
+}
+
+
+fn serialize_hex_list(palette: &NamedPalette) -> String {
+    let mut text = String::new();
+    if !palette.name.is_empty() {
+        text += &format!("; Name: {}\n", palette.name);
+    }
+    for swatch in palette.swatches.iter() {
+        text += &swatch.color.to_hex();
+        if !swatch.name.is_empty() {
+            text += " ";
+            text += &swatch.name;
+        }
+        text += "\n";
+    }
+    text
+}
+
+
+// Colors shared by all the color selectors, saved to disk so they survive restarting the app.
+// Every named palette is stored as a hex list file of its own, which unlike .gpl keeps the alpha channel.
+pub struct ColorPalette {
+    // most recent first
+    pub recent: Vec<Color>,
+    pub palettes: Vec<NamedPalette>,
+    pub selected: usize,
+    // palette files that could not be read when starting, until the app takes them
+    loading_errors: Vec<Error>,
+}
+
+impl ColorPalette {
+    const MAX_RECENT_COLORS: usize = 12;
+    const RECENT_COLORS_FILE: &str = "recent_colors.txt";
+    const PALETTES_DIRECTORY: &str = "palettes";
+    const DEFAULT_PALETTE_NAME: &str = "Default";
+    // the single palette from before there were named ones
+    const LEGACY_PALETTE_FILE: &str = "palette.txt";
+    const LEGACY_PALETTE_NAME: &str = "Saved colors";
+
+    pub fn load() -> Self {
+        let recent = storage::data_file(Self::RECENT_COLORS_FILE)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|text| parse_hex_list(&text, "").ok())
+            .map(|palette| palette.swatches.into_iter().map(|swatch| swatch.color).collect())
+            .unwrap_or_default();
+
+        let mut palettes = Vec::new();
+        let mut loading_errors = Vec::new();
+        if let Some(entries) = Self::palettes_directory().and_then(|dir| std::fs::read_dir(dir).ok()) {
+            let mut paths = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).filter(|path| path.is_file()).collect::<Vec<_>>();
+            paths.sort();
+            for path in paths.iter() {
+                let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+                match read_palette_file(path, PaletteFormat::HexList, &name) {
+                    Ok(palette) => palettes.push(palette),
+                    Err(err)    => loading_errors.push(err),
+                }
+            }
+        }
+
+        let mut color_palette = Self {
+            recent,
+            palettes,
+            selected: 0,
+            loading_errors,
+        };
+        color_palette.migrate_legacy_palette();
+        if color_palette.palettes.is_empty() {
+            color_palette.palettes.push(NamedPalette { name: String::from(Self::DEFAULT_PALETTE_NAME), swatches: Vec::new() });
+        }
+        color_palette
+    }
+
+    // the errors of the palette files that could not be loaded since the last call
+    pub fn take_loading_errors(&mut self) -> Vec<Error> {
+        std::mem::take(&mut self.loading_errors)
+    }
+
+    // Turns the colors saved by older versions into a named palette.
+    // The old file is only removed once the palette has been written to its new place.
+    fn migrate_legacy_palette(&mut self) {
+        let Some(legacy_path) = storage::data_file(Self::LEGACY_PALETTE_FILE).filter(|path| path.is_file()) else {
+            return;
+        };
+        let mut palette = match read_palette_file(&legacy_path, PaletteFormat::HexList, Self::LEGACY_PALETTE_NAME) {
+            Ok(palette) => palette,
+            Err(err)    => {
+                self.loading_errors.push(err);
+                return;
+            },
+        };
+
+        palette.name = self.unique_name(&palette.name);
+        if let Some(path) = Self::palette_path(&palette.name) {
+            match std::fs::write(&path, serialize_hex_list(&palette)) {
+                Ok(())   => {
+                    let _ = std::fs::remove_file(&legacy_path);
+                },
+                Err(err) => self.loading_errors.push(Error::io(&path, err)),
+            }
+        }
+        self.palettes.push(palette);
+    }
+
+    pub fn selected_palette(&self) -> &NamedPalette {
+        &self.palettes[self.selected]
+    }
+
+    pub fn add_recent(&mut self, color: Color) {
+        self.recent.retain(|c| *c != color);
+        self.recent.insert(0, color);
+        self.recent.truncate(Self::MAX_RECENT_COLORS);
+
+        let recent = NamedPalette {
+            name: String::new(),
+            swatches: self.recent.iter().map(|color| Swatch { color: *color, name: String::new() }).collect(),
+        };
+        if let Some(path) = storage::data_file(Self::RECENT_COLORS_FILE) {
+            let _ = std::fs::write(path, serialize_hex_list(&recent));
+        }
+    }
+
+    pub fn add_to_selected(&mut self, color: Color) {
+        let palette = &mut self.palettes[self.selected];
+        if !palette.swatches.iter().any(|swatch| swatch.color == color) {
+            palette.swatches.push(Swatch { color, name: String::new() });
+            self.save_selected();
+        }
+    }
+
+    pub fn remove_from_selected(&mut self, index: usize) {
+        self.palettes[self.selected].swatches.remove(index);
+        self.save_selected();
+    }
+
+    pub fn add_palette(&mut self, mut palette: NamedPalette) {
+        palette.name = self.unique_name(&palette.name);
+        self.palettes.push(palette);
+        self.selected = self.palettes.len() - 1;
+        self.save_selected();
+    }
+
+    // Deleting can't be undone, so it's confirmed first. Returns false when the user chose to keep the palette.
+    pub fn remove_selected_palette_if_confirmed(&mut self) -> bool {
+        let answer = MessageDialog::new()
+            .set_level(MessageLevel::Warning)
+            .set_title("Delete palette")
+            .set_description(format!("Delete the palette \"{}\"? This can't be undone.", self.selected_palette().name))
+            .set_buttons(MessageButtons::YesNo)
+            .show();
+        if answer != MessageDialogResult::Yes {
+            return false;
+        }
+        self.remove_selected_palette();
+        true
+    }
+
+    fn remove_selected_palette(&mut self) {
+        let palette = self.palettes.remove(self.selected);
+        if let Some(path) = Self::palette_path(&palette.name) {
+            let _ = std::fs::remove_file(path);
+        }
+        if self.palettes.is_empty() {
+            self.palettes.push(NamedPalette { name: String::from(Self::DEFAULT_PALETTE_NAME), swatches: Vec::new() });
+        }
+        self.selected = self.selected.min(self.palettes.len() - 1);
+    }
+
+    // Returns `Ok(false)` when the user cancelled the file dialog.
+    pub fn import_from_file_dialog(&mut self) -> Result<bool, Error> {
+        let dialog = FileDialog::new()
+            .add_filter("Palettes", &["gpl", "txt", "hex"])
+            .add_filter("All files", &["*"]);
+        let Some(path) = dialog.pick_file() else {
+            return Ok(false);
+        };
+
+        let default_name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let palette = read_palette_file(&path, PaletteFormat::from_path(&path), &default_name)?;
+        self.add_palette(palette);
+        Ok(true)
+    }
+
+    // Returns `Ok(false)` when the user cancelled, also when they chose not to lose the alpha channel in a .gpl file.
+    pub fn export_selected_with_file_dialog(&self) -> Result<bool, Error> {
+        let palette = self.selected_palette();
+        let translucent_count = palette.swatches.iter().filter(|swatch| swatch.color.alpha < 255).count();
+        // the first filter is the one that's offered, only the hex list keeps the alpha channel
+        let dialog = if translucent_count == 0 {
+            FileDialog::new()
+                .add_filter("GIMP palette", &["gpl"])
+                .add_filter("Hex list", &["txt", "hex"])
+                .set_file_name(format!("{}.gpl", palette.name))
+        }
+        else {
+            FileDialog::new()
+                .add_filter("Hex list", &["txt", "hex"])
+                .add_filter("GIMP palette", &["gpl"])
+                .set_file_name(format!("{}.txt", palette.name))
+        };
+        let Some(path) = dialog.save_file() else {
+            return Ok(false);
+        };
+
+        if translucent_count > 0 && PaletteFormat::from_path(&path) == PaletteFormat::Gpl {
+            let answer = MessageDialog::new()
+                .set_level(MessageLevel::Warning)
+                .set_title("Export palette")
+                .set_description(format!("GIMP palettes have no alpha channel, {} of the colors will become opaque. Export anyway?", translucent_count))
+                .set_buttons(MessageButtons::YesNo)
+                .show();
+            if answer != MessageDialogResult::Yes {
+                return Ok(false);
+            }
+        }
+
+        std::fs::write(&path, PaletteFormat::from_path(&path).serialize(palette)).map_err(|err| Error::io(&path, err))?;
+        Ok(true)
+    }
+
+    // The file of a palette that failed to load also takes up its name, so that it isn't overwritten.
+    fn unique_name(&self, name: &str) -> String {
+        let name = if name.trim().is_empty() { "Palette" } else { name.trim() };
+        let is_taken = |candidate: &str| {
+            self.palettes.iter().any(|palette| file_name_for(&palette.name).eq_ignore_ascii_case(&file_name_for(candidate)))
+            || Self::palette_path(candidate).is_some_and(|path| path.exists())
+        };
+        if !is_taken(name) {
+            return String::from(name);
+        }
+        (2..).map(|i| format!("{} ({})", name, i)).find(|candidate| !is_taken(candidate)).unwrap()
+    }
+
+    // Errors are ignored, losing a palette isn't worth interrupting the user over.
+    fn save_selected(&self) {
+        let palette = self.selected_palette();
+        if let Some(path) = Self::palette_path(&palette.name) {
+            let _ = std::fs::write(path, serialize_hex_list(palette));
+        }
+    }
+
+    fn palettes_directory() -> Option<PathBuf> {
+        let dir = storage::data_file(Self::PALETTES_DIRECTORY)?;
+        std::fs::create_dir_all(&dir).ok()?;
+        Some(dir)
+    }
+
+    fn palette_path(name: &str) -> Option<PathBuf> {
+        Self::palettes_directory().map(|dir| dir.join(format!("{}.txt", file_name_for(name))))
+    }
+}
+
+
+fn invalid_palette(message: String) -> Error {
+    Error::InvalidPalette { path: None, message }
+}
+
+
+fn read_palette_file(path: &Path, format: PaletteFormat, default_name: &str) -> Result<NamedPalette, Error> {
+    let text = std::fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+    format.parse(&text, default_name).map_err(|err| match err {
+        Error::InvalidPalette { message, .. } => Error::InvalidPalette { path: Some(path.to_path_buf()), message },
+        err                                   => err,
+    })
+}
+
+
+// Palette names can contain anything, file names can't.
+fn file_name_for(palette_name: &str) -> String {
+    palette_name.chars().map(|c| if c.is_alphanumeric() || " -_()".contains(c) { c } else { '_' }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swatch(color: Color, name: &str) -> Swatch {
+        Swatch { color, name: String::from(name) }
+    }
+
+    #[test]
+    fn parses_gpl() {
+        let text = "\u{feff}GIMP Palette\nName: Warm colors\nColumns: 4\n# a comment\n\n255   0   0\tBright red\n  0 128  64\n";
+        let palette = PaletteFormat::Gpl.parse(text, "file name").unwrap();
+        assert_eq!(palette.name, "Warm colors");
+        assert_eq!(palette.swatches, vec![swatch(Color::from_rgb(255, 0, 0), "Bright red"), swatch(Color::from_rgb(0, 128, 64), "")]);
+    }
+
+    #[test]
+    fn rejects_invalid_gpl() {
+        assert!(matches!(PaletteFormat::Gpl.parse("255 0 0\n", ""), Err(Error::InvalidPalette { .. })));
+        let Err(Error::InvalidPalette { message, .. }) = PaletteFormat::Gpl.parse("GIMP Palette\n1 2 3\n256 0 0\n", "") else {
+            panic!("a channel above 255 should be an error");
+        };
+        assert!(message.contains("line 3"), "{message}");
+    }
+
+    #[test]
+    fn parses_hex_list() {
+        let text = "; Name: Mixed\n; a comment\n// another one\n#ff0000 red\n00ff0080   half green \n\n#00f\n";
+        let palette = PaletteFormat::HexList.parse(text, "file name").unwrap();
+        assert_eq!(palette.name, "Mixed");
+        assert_eq!(palette.swatches, vec![
+            swatch(Color::from_rgb(255, 0, 0), "red"),
+            swatch(Color { red: 0, green: 255, blue: 0, alpha: 128 }, "half green"),
+            swatch(Color::from_rgb(0, 0, 255), ""),
+        ]);
+
+        assert_eq!(PaletteFormat::HexList.parse("#123456\n", "file name").unwrap().name, "file name");
+        let Err(Error::InvalidPalette { message, .. }) = PaletteFormat::HexList.parse("#123456\nnot a color\n", "") else {
+            panic!("a line that isn't a color should be an error");
+        };
+        assert!(message.contains("line 2"), "{message}");
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        let palette = NamedPalette {
+            name: String::from("Round trip"),
+            swatches: vec![swatch(Color::from_rgb(1, 2, 3), "first color"), swatch(Color::from_rgb(250, 251, 252), "")],
+        };
+        for format in [PaletteFormat::Gpl, PaletteFormat::HexList] {
+            assert_eq!(format.parse(&format.serialize(&palette), "").unwrap(), palette);
+        }
+
+        // only the hex list keeps the alpha channel
+        let translucent = NamedPalette { name: String::from("Translucent"), swatches: vec![swatch(Color { red: 1, green: 2, blue: 3, alpha: 4 }, "")] };
+        assert_eq!(PaletteFormat::HexList.parse(&PaletteFormat::HexList.serialize(&translucent), "").unwrap(), translucent);
+        assert_eq!(PaletteFormat::Gpl.parse(&PaletteFormat::Gpl.serialize(&translucent), "").unwrap().swatches[0].color, Color::from_rgb(1, 2, 3));
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(PaletteFormat::from_path(Path::new("colors.GPL")), PaletteFormat::Gpl);
+        assert_eq!(PaletteFormat::from_path(Path::new("colors.txt")), PaletteFormat::HexList);
+        assert_eq!(PaletteFormat::from_path(Path::new("colors")), PaletteFormat::HexList);
+    }
+}
@@ -1,4 +1,4 @@
-use std::ops::{Add, AddAssign, Mul, Sub};
+use std::ops::{Add, AddAssign, Mul, Neg, Sub};
 use std::cmp::Ordering;
 use std::marker::PhantomData;
 
@@ -13,6 +13,11 @@ impl Tag for WorldSpace {}
 pub struct ScreenSpace;
 impl Tag for ScreenSpace {}
 
+// Relative to the bounding rectangle of an object, (0, 0) is its `p1` and (1, 1) its `p2`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnitSpace;
+impl Tag for UnitSpace {}
+
 
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Number<T: Tag> {
@@ -106,6 +111,22 @@ impl<T: Tag> Vector2<T> {
         (self.x * self.x + self.y * self.y).sqrt()
     }
 
+    pub fn dot(&self, other: Self) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    // distance from the line segment between `start` and `end`
+    pub fn distance_to_segment(self, start: Self, end: Self) -> f32 {
+        let segment = end - start;
+        let length_squared = segment.length_squared();
+        if length_squared == 0.0 {
+            return (self - start).length();
+        }
+        // parameter of the point on the segment that is closest to `self`
+        let t = ((self - start).dot(segment) / length_squared).clamp(0.0, 1.0);
+        (self - (start + segment * t)).length()
+    }
+
     fn cast_to<U: Tag>(self) -> Vector2<U> {
         Vector2::<U> {
             x: self.x,
@@ -146,6 +167,18 @@ impl<T: Tag> Sub for Vector2<T> {
     }
 }
 
+impl<T: Tag> Neg for Vector2<T> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self {
+            x: -self.x,
+            y: -self.y,
+            tag: PhantomData,
+        }
+    }
+}
+
 impl<T: Tag> Mul<f32> for Vector2<T> {
     type Output = Self;
 
@@ -205,20 +238,58 @@ impl Camera {
     // }
 
     pub fn stroke_to_screen_coordinates(&self, stroke: Stroke<WorldSpace>) -> Stroke<ScreenSpace> {
+        let dash = match stroke.dash {
+            DashPattern::Solid           => DashPattern::Solid,
+            DashPattern::Dashed          => DashPattern::Dashed,
+            DashPattern::Dotted          => DashPattern::Dotted,
+            DashPattern::Custom(lengths) => DashPattern::Custom(lengths.map(|length| self.size_to_screen_coordinates(length))),
+        };
         Stroke {
             color: stroke.color,
             thickness: self.size_to_screen_coordinates(stroke.thickness),
+            dash,
+            cap: stroke.cap,
+            join: stroke.join,
         }
     }
 }
 
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum RectangleVertex {
+pub enum RectangleHandle {
     TopLeft,
+    Top,
     TopRight,
-    BottomLeft,
+    Right,
     BottomRight,
+    Bottom,
+    BottomLeft,
+    Left,
+}
+
+impl RectangleHandle {
+    pub const ALL: [Self; 8] = [Self::TopLeft, Self::Top, Self::TopRight, Self::Right, Self::BottomRight, Self::Bottom, Self::BottomLeft, Self::Left];
+
+    // which side of the rectangle the handle is on along each axis: -1.0 (p1 side), 0.0 (middle) or 1.0 (p2 side)
+    pub fn direction(self) -> (f32, f32) {
+        use RectangleHandle::*;
+
+        match self {
+            TopLeft     => (-1.0, -1.0),
+            Top         => ( 0.0, -1.0),
+            TopRight    => ( 1.0, -1.0),
+            Right       => ( 1.0,  0.0),
+            BottomRight => ( 1.0,  1.0),
+            Bottom      => ( 0.0,  1.0),
+            BottomLeft  => (-1.0,  1.0),
+            Left        => (-1.0,  0.0),
+        }
+    }
+
+    pub fn is_corner(self) -> bool {
+        let (dx, dy) = self.direction();
+        dx != 0.0 && dy != 0.0
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -253,6 +324,20 @@ impl<T: Tag> Rectangle<T> {
         }
     }
 
+    // smallest well ordered rectangle containing both rectangles
+    pub fn union(&self, other: &Self) -> Self {
+        let a = Self::from_points_well_ordered(self.p1, self.p2);
+        let b = Self::from_points_well_ordered(other.p1, other.p2);
+        Self {
+            p1: Vector2::new(a.p1.x.min(b.p1.x), a.p1.y.min(b.p1.y)),
+            p2: Vector2::new(a.p2.x.max(b.p2.x), a.p2.y.max(b.p2.y)),
+        }
+    }
+
+    pub fn center(&self) -> Vector2<T> {
+        (self.p1 + self.p2) * 0.5
+    }
+
     pub fn width(&self) -> Number<T> {
         Number::<T>::new(self.p2.x - self.p1.x)
     }
@@ -266,6 +351,19 @@ impl<T: Tag> Rectangle<T> {
         self.p1.y <= p.y && p.y <= self.p2.y
     }
 
+    // assumes that both rectangles are well ordered
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.p1.x <= other.p2.x && other.p1.x <= self.p2.x &&
+        self.p1.y <= other.p2.y && other.p1.y <= self.p2.y
+    }
+
+    pub fn expanded_by(self, margin: Number<T>) -> Self {
+        Self {
+            p1: Vector2::new(self.p1.x - margin.value, self.p1.y - margin.value),
+            p2: Vector2::new(self.p2.x + margin.value, self.p2.y + margin.value),
+        }
+    }
+
     pub fn shifted_with(self, v: Vector2<T>) -> Self {
         Self {
             p1: self.p1 + v,
@@ -280,58 +378,152 @@ impl<T: Tag> Rectangle<T> {
          Vector2::new(self.p1.x, self.p2.y)]
     }
 
-    pub fn vertex_under_point(&self, point: Vector2<T>, radius: Number<T>) -> Option<RectangleVertex> {
-        fn is_under_point<U: Tag>(vertex: Vector2<U>, point: Vector2<U>, radius: Number<U>) -> bool {
-            (vertex - point).length_squared() < radius.value * radius.value
-        }
+    // `p1` is treated as the top left corner and `p2` as the bottom right one, even if the rectangle is not well ordered
+    pub fn handle(&self, handle: RectangleHandle) -> Vector2<T> {
+        let (dx, dy) = handle.direction();
+        let c = self.center();
+        Vector2::new(c.x + dx * (self.p2.x - self.p1.x) * 0.5,
+                     c.y + dy * (self.p2.y - self.p1.y) * 0.5)
+    }
+
+    pub fn handle_under_point(&self, point: Vector2<T>, radius: Number<T>) -> Option<RectangleHandle> {
+        // corners first, so that they win over the edges of small rectangles
+        RectangleHandle::ALL.into_iter()
+                            .filter(|handle| handle.is_corner())
+                            .chain(RectangleHandle::ALL.into_iter().filter(|handle| !handle.is_corner()))
+                            .find(|handle| (self.handle(*handle) - point).length_squared() < radius.value * radius.value)
+    }
 
-        use RectangleVertex::*;
-        
-        if is_under_point(self.p1, point, radius) {
-            Some(TopLeft)
+    // Corner handles move both coordinates, edge handles only one.
+    // When `keep_aspect_ratio` is set the rectangle is scaled uniformly (by the larger of the two scales in case of a corner),
+    // when `around_center` is set the opposite side moves symmetrically, so the center stays in place.
+    // Dragging a side past the opposite one results in a rectangle that is not well ordered, i.e. a flipped one.
+    pub fn resize_by_dragging_handle(&self, handle: RectangleHandle, drag_delta: Vector2<T>, keep_aspect_ratio: bool, around_center: bool) -> Self {
+        fn resize_axis(a: f32, b: f32, direction: f32, delta: f32, around_center: bool) -> (f32, f32) {
+            let opposite_delta = if around_center { -delta } else { 0.0 };
+            if direction < 0.0 {
+                (a + delta, b + opposite_delta)
+            }
+            else if direction > 0.0 {
+                (a + opposite_delta, b + delta)
+            }
+            else {
+                (a, b)
+            }
         }
-        else if is_under_point(self.p2, point, radius) {
-            Some(BottomRight)
+
+        let (dx, dy) = handle.direction();
+        let (x1, x2) = resize_axis(self.p1.x, self.p2.x, dx, drag_delta.x, around_center);
+        let (y1, y2) = resize_axis(self.p1.y, self.p2.y, dy, drag_delta.y, around_center);
+        let resized = Self {
+            p1: Vector2::new(x1, y1),
+            p2: Vector2::new(x2, y2),
+        };
+
+        let old_width  = self.p2.x - self.p1.x;
+        let old_height = self.p2.y - self.p1.y;
+        if !keep_aspect_ratio || old_width == 0.0 || old_height == 0.0 {
+            return resized;
         }
-        else if is_under_point(Vector2::new(self.p2.x, self.p1.y), point, radius) {
-            Some(TopRight)
+
+        let scale_x = (x2 - x1) / old_width;
+        let scale_y = (y2 - y1) / old_height;
+        let scale = if handle.is_corner() {
+            scale_x.abs().max(scale_y.abs())
         }
-        else if is_under_point(Vector2::new(self.p1.x, self.p2.y), point, radius) {
-            Some(BottomLeft)
+        else if dx != 0.0 {
+            scale_x.abs()
         }
         else {
-            None
+            scale_y.abs()
+        };
+
+        // The side opposite to the handle stays in place (or the center, if resizing around it).
+        // Along an axis the handle doesn't move, the rectangle is scaled around its center.
+        let anchor = |a: f32, b: f32, direction: f32| {
+            if around_center || direction == 0.0 {
+                (a + b) * 0.5
+            }
+            else if direction > 0.0 {
+                a
+            }
+            else {
+                b
+            }
+        };
+        let ax = anchor(self.p1.x, self.p2.x, dx);
+        let ay = anchor(self.p1.y, self.p2.y, dy);
+        let sx = scale * if scale_x < 0.0 { -1.0 } else { 1.0 };
+        let sy = scale * if scale_y < 0.0 { -1.0 } else { 1.0 };
+
+        Self {
+            p1: Vector2::new(ax + (self.p1.x - ax) * sx, ay + (self.p1.y - ay) * sy),
+            p2: Vector2::new(ax + (self.p2.x - ax) * sx, ay + (self.p2.y - ay) * sy),
         }
     }
 
-    pub fn resize_by_dragging_vertex(&self, vertex: RectangleVertex, drag_delta: Vector2<T>) -> Self {
-        use RectangleVertex::*;
+    // Maps `point` linearly from this rectangle to `target`, so that `p1` goes to `target.p1` and `p2` goes to `target.p2`.
+    // Along an axis where this rectangle has zero size the point is only moved together with `p1`.
+    pub fn map_point_to(&self, point: Vector2<T>, target: &Self) -> Vector2<T> {
+        fn map_axis(x: f32, a: f32, b: f32, target_a: f32, target_b: f32) -> f32 {
+            if a == b {
+                target_a + (x - a)
+            }
+            else {
+                target_a + (x - a) * (target_b - target_a) / (b - a)
+            }
+        }
 
-        match vertex {
-            TopLeft => {
-                Self {
-                    p1: self.p1 + drag_delta,
-                    p2: self.p2,
-                }
-            },
-            TopRight => {
-                Self {
-                    p1: Vector2::new(self.p1.x, self.p1.y + drag_delta.y),
-                    p2: Vector2::new(self.p2.x + drag_delta.x, self.p2.y),
-                }
-            },
-            BottomLeft => {
-                Self {
-                    p1: Vector2::new(self.p1.x + drag_delta.x, self.p1.y),
-                    p2: Vector2::new(self.p2.x, self.p2.y + drag_delta.y),
-                }
-            },
-            BottomRight => {
-                Self {
-                    p1: self.p1,
-                    p2: self.p2 + drag_delta,
+        Vector2::new(map_axis(point.x, self.p1.x, self.p2.x, target.p1.x, target.p2.x),
+                     map_axis(point.y, self.p1.y, self.p2.y, target.p1.y, target.p2.y))
+    }
+
+    pub fn point_to_unit(&self, point: Vector2<T>) -> Vector2<UnitSpace> {
+        fn to_unit(x: f32, a: f32, b: f32) -> f32 {
+            if a == b { 0.0 } else { (x - a) / (b - a) }
+        }
+
+        Vector2::new(to_unit(point.x, self.p1.x, self.p2.x), to_unit(point.y, self.p1.y, self.p2.y))
+    }
+
+    pub fn point_from_unit(&self, point: Vector2<UnitSpace>) -> Vector2<T> {
+        Vector2::new(self.p1.x + point.x * (self.p2.x - self.p1.x),
+                     self.p1.y + point.y * (self.p2.y - self.p1.y))
+    }
+
+    // Sutherland-Hodgman, assumes that the rectangle is well ordered.
+    // The result might be empty, or degenerate where the polygon only touches the rectangle.
+    pub fn clip_polygon(&self, polygon: &[Vector2<T>]) -> Vec<Vector2<T>> {
+        // each side as (whether it's a vertical side, its coordinate, whether the inside is on the greater side)
+        let sides = [(true, self.p1.x, true), (true, self.p2.x, false), (false, self.p1.y, true), (false, self.p2.y, false)];
+
+        let mut result = polygon.to_vec();
+        for (is_vertical, bound, inside_is_greater) in sides {
+            let coordinate = |p: Vector2<T>| if is_vertical { p.x } else { p.y };
+            let is_inside = |p: Vector2<T>| if inside_is_greater { coordinate(p) >= bound } else { coordinate(p) <= bound };
+            let intersection = |a: Vector2<T>, b: Vector2<T>| a + (b - a) * ((bound - coordinate(a)) / (coordinate(b) - coordinate(a)));
+
+            let input = std::mem::take(&mut result);
+            for (i, current) in input.iter().enumerate() {
+                let previous = input[(i + input.len() - 1) % input.len()];
+                match (is_inside(previous), is_inside(*current)) {
+                    (true, true)   => result.push(*current),
+                    (true, false)  => result.push(intersection(previous, *current)),
+                    (false, true)  => {
+                        result.push(intersection(previous, *current));
+                        result.push(*current);
+                    },
+                    (false, false) => {},
                 }
-            },
+            }
+        }
+        result
+    }
+
+    pub fn map_rect_to(&self, rect: Self, target: &Self) -> Self {
+        Self {
+            p1: self.map_point_to(rect.p1, target),
+            p2: self.map_point_to(rect.p2, target),
         }
     }
 
@@ -419,7 +611,16 @@ impl<T: Tag> Rectangle<T> {
 }
 
 
-#[derive(Clone, Copy)]
+// which way an image is mirrored when drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Flip {
+    pub horizontal: bool,
+    pub vertical: bool,
+}
+
+
+// sRGB with straight (not premultiplied) alpha
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Color {
     pub red: u8,
     pub green: u8,
@@ -437,6 +638,20 @@ impl Color {
         }
     }
 
+    // `t` = 0.0 gives `self`, `t` = 1.0 gives `other`
+    pub fn mixed_with(self, other: Self, t: f32) -> Self {
+        fn mix(a: u8, b: u8, t: f32) -> u8 {
+            (a as f32 + (b as f32 - a as f32) * t).round() as u8
+        }
+
+        Self {
+            red: mix(self.red, other.red, t),
+            green: mix(self.green, other.green, t),
+            blue: mix(self.blue, other.blue, t),
+            alpha: mix(self.alpha, other.alpha, t),
+        }
+    }
+
     pub fn inverse(self) -> Self {
         Self {
             red: 255 - self.red,
@@ -445,13 +660,111 @@ impl Color {
             alpha: self.alpha,
         }
     }
+
+    // Alpha compositing, the color that is seen when `self` is drawn on top of `background`.
+    pub fn over(self, background: Self) -> Self {
+        let a  = self.alpha as f32 / 255.0;
+        let ba = background.alpha as f32 / 255.0 * (1.0 - a);
+        let alpha = a + ba;
+        if alpha <= 0.0 {
+            return Self { red: 0, green: 0, blue: 0, alpha: 0 };
+        }
+        let mix = |c: u8, bc: u8| ((c as f32 * a + bc as f32 * ba) / alpha).round() as u8;
+
+        Self {
+            red: mix(self.red, background.red),
+            green: mix(self.green, background.green),
+            blue: mix(self.blue, background.blue),
+            alpha: (alpha * 255.0).round() as u8,
+        }
+    }
+
+    // "#rrggbb" for opaque colors, "#rrggbbaa" otherwise
+    pub fn to_hex(self) -> String {
+        if self.alpha == 255 {
+            format!("#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+        }
+        else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", self.red, self.green, self.blue, self.alpha)
+        }
+    }
+
+    // Accepts "rgb", "rrggbb" and "rrggbbaa", with or without a leading '#'.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim();
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        // `from_str_radix` would also accept a sign
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok();
+        match hex.len() {
+            3 => {
+                let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok().map(|d| d * 17);
+                Some(Self::from_rgb(digit(0)?, digit(1)?, digit(2)?))
+            },
+            6 => Some(Self::from_rgb(channel(0)?, channel(1)?, channel(2)?)),
+            8 => Some(Self { red: channel(0)?, green: channel(1)?, blue: channel(2)?, alpha: channel(3)? }),
+            _ => None,
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DashPattern<T: Tag> {
+    Solid,
+    Dashed,
+    Dotted,
+    // dash, gap, dash, gap
+    Custom([Number<T>; 4]),
+}
+
+impl<T: Tag> DashPattern<T> {
+    // Lengths of the dashes and gaps, `None` for a solid line.
+    // The predefined patterns are relative to the thickness of the line.
+    pub fn lengths(&self, thickness: Number<T>) -> Option<[f32; 4]> {
+        let t = thickness.value;
+        match self {
+            Self::Solid           => None,
+            Self::Dashed          => Some([4.0 * t, 2.0 * t, 4.0 * t, 2.0 * t]),
+            Self::Dotted          => Some([t, t, t, t]),
+            Self::Custom(lengths) => Some(lengths.map(|length| length.value.max(0.0))),
+        }
+    }
 }
 
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineCap {
+    #[default]
+    Butt,
+    Round,
+    Square,
+}
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineJoin {
+    #[default]
+    Miter,
+    Round,
+    Bevel,
+}
+
+impl LineJoin {
+    // the default of SVG, longer miters are cut off like bevels
+    pub const MITER_LIMIT: f32 = 4.0;
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Stroke<T: Tag> {
     pub color: Color,
     pub thickness: Number<T>,
+    pub dash: DashPattern<T>,
+    pub cap: LineCap,
+    pub join: LineJoin,
 }
 
 impl<T: Tag> Stroke<T> {
@@ -459,6 +772,568 @@ impl<T: Tag> Stroke<T> {
         Self {
             color,
             thickness,
+            dash: DashPattern::Solid,
+            cap: LineCap::default(),
+            join: LineJoin::default(),
+        }
+    }
+
+    // How far the drawn stroke can reach past the path it follows: half the thickness, more at square caps and miter joins.
+    pub fn reach(&self) -> Number<T> {
+        let factor = match (self.join, self.cap) {
+            (LineJoin::Miter, _) => LineJoin::MITER_LIMIT,
+            (_, LineCap::Square) => std::f32::consts::SQRT_2,
+            _                    => 1.0,
+        };
+        self.thickness * (0.5 * factor)
+    }
+}
+
+
+// Splits the polyline into the pieces that are drawn with the given dash pattern (dash, gap, dash, gap, ...).
+// The pattern continues over the vertices, it doesn't restart at each segment.
+pub fn split_into_dashes<T: Tag>(points: &[Vector2<T>], pattern: [f32; 4]) -> Vec<Vec<Vector2<T>>> {
+    if pattern.iter().sum::<f32>() <= 0.0 || points.is_empty() {
+        return vec![points.to_vec()];
+    }
+
+    let mut dashes = Vec::new();
+    let mut current_dash = vec![points[0]];
+    let mut pattern_index = 0;
+    let mut remaining = pattern[0];
+    let mut is_dash = true;
+
+    for p1p2 in points.windows(2) {
+        let (start, end) = (p1p2[0], p1p2[1]);
+        let segment_length = (end - start).length();
+        if segment_length == 0.0 {
+            continue;
+        }
+        let mut position = 0.0;
+        loop {
+            if remaining > segment_length - position {
+                remaining -= segment_length - position;
+                // a dash that starts exactly at the vertex already has it
+                if is_dash && current_dash.last().is_none_or(|last| (end - *last).length_squared() > 0.0) {
+                    current_dash.push(end);
+                }
+                break;
+            }
+            position += remaining;
+            let p = start + (end - start) * (position / segment_length);
+            if is_dash {
+                current_dash.push(p);
+                dashes.push(std::mem::take(&mut current_dash));
+            }
+            else {
+                current_dash.push(p);
+            }
+            is_dash = !is_dash;
+            pattern_index = (pattern_index + 1) % pattern.len();
+            remaining = pattern[pattern_index];
+        }
+    }
+    if is_dash && current_dash.len() > 1 {
+        dashes.push(current_dash);
+    }
+
+    dashes
+}
+
+
+// The area covered by a stroke as polygons that all go around the same way, so that filling them with the nonzero rule gives their union.
+// Same caps and joins as `EguiPainter::draw_solid_polyline`.
+pub fn stroke_outline<T: Tag>(points: &[Vector2<T>], stroke: Stroke<T>) -> Vec<Vec<Vector2<T>>> {
+    let mut paths = Vec::new();
+    if let Some(pattern) = stroke.dash.lengths(stroke.thickness) {
+        for dash in split_into_dashes(points, pattern) {
+            add_solid_stroke_outline(&dash, stroke, &mut paths);
+        }
+    }
+    else {
+        add_solid_stroke_outline(points, stroke, &mut paths);
+    }
+    for path in paths.iter_mut() {
+        if signed_area(path) < 0.0 {
+            path.reverse();
         }
     }
+    paths
+}
+
+
+fn add_solid_stroke_outline<T: Tag>(points: &[Vector2<T>], stroke: Stroke<T>, paths: &mut Vec<Vec<Vector2<T>>>) {
+    let mut points = points.to_vec();
+    points.dedup_by(|a, b| a.x == b.x && a.y == b.y);
+    let radius = stroke.thickness.value * 0.5;
+
+    let (Some(first), Some(last)) = (points.first().copied(), points.last().copied()) else {
+        return;
+    };
+    if points.len() == 1 {
+        // zero length dash (or line), only its caps are visible
+        match stroke.cap {
+            LineCap::Butt   => {},
+            LineCap::Round  => paths.push(circle(first, radius)),
+            LineCap::Square => paths.push(Rectangle::from_center_and_side_length(first, stroke.thickness).vertices().to_vec()),
+        }
+        return;
+    }
+
+    if stroke.cap == LineCap::Square {
+        let n = points.len();
+        points[0]     = points[0] + unit_direction(points[1], points[0]) * radius;
+        points[n - 1] = points[n - 1] + unit_direction(points[n - 2], points[n - 1]) * radius;
+    }
+
+    for p in points.windows(2) {
+        let n = unit_normal(p[0], p[1]) * radius;
+        paths.push(vec![p[0] + n, p[1] + n, p[1] - n, p[0] - n]);
+    }
+    for p in points.windows(3) {
+        let n1 = unit_normal(p[0], p[1]) * radius;
+        let n2 = unit_normal(p[1], p[2]) * radius;
+        match stroke.join {
+            LineJoin::Round => paths.push(circle(p[1], radius)),
+            LineJoin::Bevel => paths.push(vec![p[1] + n1, p[1] + n2, p[1] - n1, p[1] - n2]),
+            LineJoin::Miter => {
+                paths.push(vec![p[1] + n1, p[1] + n2, p[1] - n1, p[1] - n2]);
+                // the tip in front of the bevel, on the outer side of the turn
+                let side = if (p[2] - p[1]).dot(n1) > 0.0 { -1.0 } else { 1.0 };
+                let bisector = n1 + n2;
+                let bisector_length = bisector.length();
+                if bisector_length > 0.0 {
+                    let miter_length = 2.0 * radius * radius / bisector_length;
+                    if miter_length <= LineJoin::MITER_LIMIT * radius {
+                        let tip = p[1] + bisector * (side * miter_length / bisector_length);
+                        paths.push(vec![p[1] + n1 * side, tip, p[1] + n2 * side]);
+                    }
+                }
+            },
+        }
+    }
+
+    if stroke.cap == LineCap::Round {
+        paths.push(circle(first, radius));
+        paths.push(circle(last, radius));
+    }
+}
+
+
+// Whether the stroke drawn along `points` covers `point`, gaps between dashes don't.
+pub fn stroke_contains_point<T: Tag>(points: &[Vector2<T>], stroke: Stroke<T>, point: Vector2<T>) -> bool {
+    stroke_outline(points, stroke).iter().any(|path| polygon_contains_point(path, point))
+}
+
+
+// from `a` towards `b`, with a length of 1
+fn unit_direction<T: Tag>(a: Vector2<T>, b: Vector2<T>) -> Vector2<T> {
+    let d = b - a;
+    d * (1.0 / d.length())
+}
+
+
+// perpendicular to the segment from `a` to `b`, with a length of 1
+fn unit_normal<T: Tag>(a: Vector2<T>, b: Vector2<T>) -> Vector2<T> {
+    let d = unit_direction(a, b);
+    Vector2::new(-d.y, d.x)
+}
+
+
+pub fn circle<T: Tag>(center: Vector2<T>, radius: f32) -> Vec<Vector2<T>> {
+    let segments = ((radius.max(0.0).sqrt() * 4.0) as usize).clamp(8, 64);
+    (0..segments).map(|i| {
+        let angle = i as f32 / segments as f32 * std::f32::consts::TAU;
+        center + Vector2::new(radius * angle.cos(), radius * angle.sin())
+    }).collect()
+}
+
+
+fn signed_area<T: Tag>(path: &[Vector2<T>]) -> f32 {
+    (0..path.len()).map(|i| {
+        let (a, b) = (path[i], path[(i + 1) % path.len()]);
+        a.x * b.y - b.x * a.y
+    }).sum::<f32>() * 0.5
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GradientStop {
+    // 0.0 is the start of the gradient, 1.0 is the end
+    pub offset: f32,
+    pub color: Color,
+}
+
+
+// The points are relative to the bounding rectangle of the filled object,
+// so the gradient moves, scales and flips together with the object.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    // the center for radial gradients
+    pub start: Vector2<UnitSpace>,
+    // a point on the outermost circle for radial gradients
+    pub end: Vector2<UnitSpace>,
+    // sorted by offset
+    pub stops: Vec<GradientStop>,
+}
+
+impl Gradient {
+    pub fn new(start: Vector2<UnitSpace>, end: Vector2<UnitSpace>, start_color: Color, end_color: Color) -> Self {
+        Self {
+            start,
+            end,
+            stops: vec![
+                GradientStop { offset: 0.0, color: start_color },
+                GradientStop { offset: 1.0, color: end_color },
+            ],
+        }
+    }
+
+    pub fn color_at_offset(&self, offset: f32) -> Color {
+        let Some(first) = self.stops.first() else {
+            return Color { red: 0, green: 0, blue: 0, alpha: 0 };
+        };
+        if offset <= first.offset {
+            return first.color;
+        }
+        for s1s2 in self.stops.windows(2) {
+            let (s1, s2) = (s1s2[0], s1s2[1]);
+            if offset <= s2.offset {
+                let t = if s2.offset > s1.offset { (offset - s1.offset) / (s2.offset - s1.offset) } else { 1.0 };
+                return s1.color.mixed_with(s2.color, t);
+            }
+        }
+        self.stops[self.stops.len() - 1].color
+    }
+
+    pub fn sort_stops(&mut self) {
+        self.stops.sort_by(|a, b| a.offset.partial_cmp(&b.offset).unwrap_or(Ordering::Equal));
+    }
+
+    fn mirror(&mut self, flip: Flip) {
+        for p in [&mut self.start, &mut self.end] {
+            if flip.horizontal {
+                p.x = 1.0 - p.x;
+            }
+            if flip.vertical {
+                p.y = 1.0 - p.y;
+            }
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Fill {
+    #[default]
+    None,
+    Solid(Color),
+    LinearGradient(Gradient),
+    RadialGradient(Gradient),
+}
+
+impl Fill {
+    // `point` is relative to the bounding rectangle of the filled object
+    pub fn color_at(&self, point: Vector2<UnitSpace>) -> Option<Color> {
+        match self {
+            Self::None                     => None,
+            Self::Solid(color)             => Some(*color),
+            Self::LinearGradient(gradient) => {
+                let axis = gradient.end - gradient.start;
+                let length_squared = axis.dot(axis);
+                let t = if length_squared > 0.0 { (point - gradient.start).dot(axis) / length_squared } else { 0.0 };
+                Some(gradient.color_at_offset(t))
+            },
+            Self::RadialGradient(gradient) => {
+                let radius = (gradient.end - gradient.start).length();
+                let t = if radius > 0.0 { (point - gradient.start).length() / radius } else { 0.0 };
+                Some(gradient.color_at_offset(t))
+            },
+        }
+    }
+
+    pub fn gradient(&self) -> Option<&Gradient> {
+        match self {
+            Self::LinearGradient(gradient) | Self::RadialGradient(gradient) => Some(gradient),
+            _                                                               => None,
+        }
+    }
+
+    pub fn gradient_mut(&mut self) -> Option<&mut Gradient> {
+        match self {
+            Self::LinearGradient(gradient) | Self::RadialGradient(gradient) => Some(gradient),
+            _                                                               => None,
+        }
+    }
+
+    // Has to be called when the filled object is mirrored, because the gradient is relative to its bounding rectangle.
+    pub fn mirror(&mut self, flip: Flip) {
+        if let Some(gradient) = self.gradient_mut() {
+            gradient.mirror(flip);
+        }
+    }
+}
+
+
+// Non-destructive changes to the colors of a picture, they are applied to a copy of the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageAdjustments {
+    pub brightness: f32, // -1.0..=1.0, added to every channel
+    pub contrast: f32,   // -1.0..=1.0, -1.0 makes everything gray
+    pub saturation: f32, // -1.0..=1.0, -1.0 is the same as grayscale
+    pub grayscale: bool,
+    pub invert: bool,
+    pub opacity: f32,    // 0.0..=1.0
+}
+
+impl Default for ImageAdjustments {
+    fn default() -> Self {
+        Self {
+            brightness: 0.0,
+            contrast: 0.0,
+            saturation: 0.0,
+            grayscale: false,
+            invert: false,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl ImageAdjustments {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn apply_to(&self, color: Color) -> Color {
+        // steep, but finite at the end of the range
+        let contrast_factor = if self.contrast >= 0.0 { 1.0 / (1.0 - self.contrast).max(0.01) } else { 1.0 + self.contrast };
+        let saturation_factor = if self.grayscale { 0.0 } else { 1.0 + self.saturation };
+
+        let mut rgb = [color.red, color.green, color.blue].map(|c| {
+            let c = c as f32 / 255.0 + self.brightness;
+            (c - 0.5) * contrast_factor + 0.5
+        });
+        if saturation_factor != 1.0 {
+            let luma = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+            rgb = rgb.map(|c| luma + (c - luma) * saturation_factor);
+        }
+        let [red, green, blue] = rgb.map(|c| {
+            let c = c.clamp(0.0, 1.0);
+            ((if self.invert { 1.0 - c } else { c }) * 255.0).round() as u8
+        });
+
+        Color {
+            red,
+            green,
+            blue,
+            alpha: (color.alpha as f32 * self.opacity.clamp(0.0, 1.0)).round() as u8,
+        }
+    }
+}
+
+
+pub fn polygon_contains_point<T: Tag>(polygon: &[Vector2<T>], point: Vector2<T>) -> bool {
+    // even-odd rule
+    let mut is_inside = false;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        if (a.y > point.y) != (b.y > point.y) && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            is_inside = !is_inside;
+        }
+    }
+    is_inside
+}
+
+
+// Ear clipping, returns the indices of the triangles' vertices.
+// Self-intersecting polygons don't have a proper triangulation, whatever is left of them when no ear can be found is fanned out.
+pub fn triangulate_polygon<T: Tag>(polygon: &[Vector2<T>]) -> Vec<[usize; 3]> {
+    fn cross<T: Tag>(a: Vector2<T>, b: Vector2<T>, c: Vector2<T>) -> f32 {
+        (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+    }
+
+    let mut remaining = (0..polygon.len()).collect::<Vec<_>>();
+    let mut triangles = Vec::new();
+    if polygon.len() < 3 {
+        return triangles;
+    }
+
+    // the ears are the convex corners, which depends on the winding order
+    let signed_area = (0..polygon.len()).map(|i| {
+        let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+        a.x * b.y - b.x * a.y
+    }).sum::<f32>();
+    let orientation = if signed_area >= 0.0 { 1.0 } else { -1.0 };
+
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let corner = [remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]];
+            let [a, b, c] = corner.map(|j| polygon[j]);
+            if cross(a, b, c) * orientation <= 0.0 {
+                return false;
+            }
+            // no other vertex may be inside the ear
+            remaining.iter().filter(|j| !corner.contains(j)).map(|&j| polygon[j]).all(|p| {
+                cross(a, b, p) * orientation < 0.0 || cross(b, c, p) * orientation < 0.0 || cross(c, a, p) * orientation < 0.0
+            })
+        });
+
+        match ear {
+            Some(i) => {
+                triangles.push([remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]]);
+                remaining.remove(i);
+            },
+            None => {
+                for i in 1..n - 1 {
+                    triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+                }
+                return triangles;
+            },
+        }
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(coordinates: &[(f32, f32)]) -> Vec<Vector2<WorldSpace>> {
+        coordinates.iter().map(|(x, y)| Vector2::new(*x, *y)).collect()
+    }
+
+    #[test]
+    fn dashes_continue_over_vertices() {
+        let line = points(&[(0.0, 0.0), (5.0, 0.0), (5.0, 5.0)]);
+        let dashes = split_into_dashes(&line, [3.0, 1.0, 3.0, 1.0]);
+        assert_eq!(dashes, vec![
+            points(&[(0.0, 0.0), (3.0, 0.0)]),
+            points(&[(4.0, 0.0), (5.0, 0.0), (5.0, 2.0)]),
+            points(&[(5.0, 3.0), (5.0, 5.0)]),
+        ]);
+    }
+
+    #[test]
+    fn dashes_use_the_whole_pattern() {
+        let line = points(&[(0.0, 0.0), (10.0, 0.0)]);
+        let dashes = split_into_dashes(&line, [1.0, 1.0, 4.0, 2.0]);
+        assert_eq!(dashes, vec![
+            points(&[(0.0, 0.0), (1.0, 0.0)]),
+            points(&[(2.0, 0.0), (6.0, 0.0)]),
+            points(&[(8.0, 0.0), (9.0, 0.0)]),
+        ]);
+    }
+
+    #[test]
+    fn empty_pattern_or_line_is_not_split() {
+        let line = points(&[(0.0, 0.0), (10.0, 0.0)]);
+        assert_eq!(split_into_dashes(&line, [0.0; 4]), vec![line.clone()]);
+        assert_eq!(split_into_dashes::<WorldSpace>(&[], [1.0; 4]), vec![Vec::new()]);
+        // zero length segments are skipped
+        let line = points(&[(0.0, 0.0), (0.0, 0.0), (2.0, 0.0)]);
+        assert_eq!(split_into_dashes(&line, [1.0; 4]), vec![points(&[(0.0, 0.0), (1.0, 0.0)])]);
+    }
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(Color::from_hex("#ff8000"), Some(Color::from_rgb(255, 128, 0)));
+        assert_eq!(Color::from_hex(" FF8000 "), Some(Color::from_rgb(255, 128, 0)));
+        assert_eq!(Color::from_hex("#f80"), Some(Color::from_rgb(255, 136, 0)));
+        assert_eq!(Color::from_hex("ff800040"), Some(Color { red: 255, green: 128, blue: 0, alpha: 64 }));
+        for invalid in ["", "#", "ff80", "#ff80000", "gg8000", "#ff80000g", "ffé00", "+f8", "+f8000"] {
+            assert_eq!(Color::from_hex(invalid), None, "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        for color in [Color::from_rgb(1, 2, 3), Color { red: 250, green: 0, blue: 17, alpha: 128 }] {
+            assert_eq!(Color::from_hex(&color.to_hex()), Some(color));
+        }
+        assert_eq!(Color::from_rgb(255, 0, 16).to_hex(), "#ff0010");
+    }
+
+    fn area(polygon: &[Vector2<WorldSpace>]) -> f32 {
+        (0..polygon.len()).map(|i| {
+            let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+            a.x * b.y - b.x * a.y
+        }).sum::<f32>() * 0.5
+    }
+
+    #[test]
+    fn triangulation_covers_concave_polygons() {
+        // an L shape in both winding orders
+        let mut polygon = points(&[(0.0, 0.0), (4.0, 0.0), (4.0, 1.0), (1.0, 1.0), (1.0, 3.0), (0.0, 3.0)]);
+        for _ in 0..2 {
+            let triangles = triangulate_polygon(&polygon);
+            assert_eq!(triangles.len(), polygon.len() - 2);
+            // the triangles have the winding of the polygon, so none of them covers the notch
+            for triangle in triangles.iter() {
+                assert!(area(&triangle.map(|i| polygon[i])) * area(&polygon).signum() > 0.0);
+            }
+            let total = triangles.iter().map(|triangle| area(&triangle.map(|i| polygon[i])).abs()).sum::<f32>();
+            assert_eq!(total, area(&polygon).abs());
+            polygon.reverse();
+        }
+        assert!(triangulate_polygon(&points(&[(0.0, 0.0), (1.0, 0.0)])).is_empty());
+    }
+
+    #[test]
+    fn polygons_are_clipped_to_the_rectangle() {
+        let rect = Rectangle::<WorldSpace> { p1: Vector2::new(0.0, 0.0), p2: Vector2::new(2.0, 2.0) };
+        let diamond = points(&[(1.0, -0.5), (2.5, 1.0), (1.0, 2.5), (-0.5, 1.0)]);
+        let clipped = rect.clip_polygon(&diamond);
+        assert_eq!(clipped.len(), 8);
+        // the corners of the square are cut off
+        assert_eq!(area(&clipped).abs(), 4.0 - 4.0 * 0.125);
+        assert!(clipped.iter().all(|p| (0.0..=2.0).contains(&p.x) && (0.0..=2.0).contains(&p.y)));
+
+        let inside = points(&[(0.5, 0.5), (1.5, 0.5), (1.0, 1.5)]);
+        assert_eq!(rect.clip_polygon(&inside), inside);
+        assert!(rect.clip_polygon(&points(&[(5.0, 5.0), (6.0, 5.0), (6.0, 6.0)])).is_empty());
+    }
+
+    #[test]
+    fn strokes_cover_their_dashes_and_caps() {
+        let line = points(&[(0.0, 0.0), (10.0, 0.0)]);
+        let mut stroke = Stroke::<WorldSpace>::new(Color::from_rgb(0, 0, 0), Number::new(2.0));
+        let covers = |stroke: Stroke<WorldSpace>, x: f32, y: f32| stroke_contains_point(&line, stroke, Vector2::new(x, y));
+
+        assert!(covers(stroke, 5.0, 0.9));
+        assert!(!covers(stroke, 5.0, 1.1));
+        // butt caps end at the end points
+        assert!(!covers(stroke, -0.5, 0.0));
+        stroke.cap = LineCap::Square;
+        assert!(covers(stroke, -0.9, 0.9));
+        assert!(!covers(stroke, -1.1, 0.0));
+        stroke.cap = LineCap::Round;
+        assert!(covers(stroke, -0.9, 0.0));
+        assert!(!covers(stroke, -0.9, 0.9));
+
+        // dashes of 4 and gaps of 2 with a thickness of 2, the round caps reach 1 into the gaps
+        stroke.dash = DashPattern::Custom([Number::new(4.0), Number::new(2.0), Number::new(4.0), Number::new(2.0)]);
+        assert!(covers(stroke, 4.5, 0.0));
+        assert!(!covers(stroke, 5.0, 0.5));
+        stroke.cap = LineCap::Butt;
+        assert!(!covers(stroke, 4.5, 0.0));
+        assert!(covers(stroke, 6.5, 0.0));
+    }
+
+    #[test]
+    fn strokes_cover_their_joins() {
+        let corner = points(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        let mut stroke = Stroke::<WorldSpace>::new(Color::from_rgb(0, 0, 0), Number::new(2.0));
+        let outer_corner = Vector2::new(10.9, -0.9);
+
+        stroke.join = LineJoin::Miter;
+        assert!(stroke_contains_point(&corner, stroke, outer_corner));
+        stroke.join = LineJoin::Round;
+        assert!(!stroke_contains_point(&corner, stroke, outer_corner));
+        assert!(stroke_contains_point(&corner, stroke, Vector2::new(10.6, -0.6)));
+        stroke.join = LineJoin::Bevel;
+        assert!(!stroke_contains_point(&corner, stroke, Vector2::new(10.6, -0.6)));
+        assert!(stroke_contains_point(&corner, stroke, Vector2::new(10.4, -0.4)));
+    }
 }
@@ -0,0 +1,315 @@
+use eframe::egui;
+use crate::primitives::*;
+use crate::engine::*;
+use crate::egui_painter::EguiPainter;
+use crate::paint_object::picture::path_from_open_file_dialog;
+
+
+// Side panel for editing the style and the exact geometry of the selected objects.
+// Edits are written back to every selected object, but only the property that was actually changed,
+// so e.g. changing the thickness of several lines keeps their different colors.
+pub fn show(ctx: &egui::Context, engine: &mut Engine<EguiPainter, egui::ImageSource<'static>>, is_enabled: bool) {
+    let Some(selection_rect) = engine.selection_rect() else {
+        return;
+    };
+
+    egui::SidePanel::right("properties").resizable(false).show(ctx, |ui| {
+        if !is_enabled {
+            ui.disable();
+        }
+        ui.heading("Properties");
+
+        if let Some((stroke, is_common)) = engine.selection_stroke() {
+            ui.separator();
+            ui.label(if is_common { "stroke" } else { "stroke (mixed)" });
+
+            let mut rgb = [stroke.color.red, stroke.color.green, stroke.color.blue];
+            ui.horizontal(|ui| {
+                ui.label("color");
+                if ui.color_edit_button_srgb(&mut rgb).changed() {
+                    engine.edit_selection_strokes(|s| {
+                        s.color.red   = rgb[0];
+                        s.color.green = rgb[1];
+                        s.color.blue  = rgb[2];
+                    });
+                }
+            });
+
+            let mut thickness = stroke.thickness.value;
+            if ui.add(egui::Slider::new(&mut thickness, 0.5..=MAXIMUM_STROKE_THICKNESS.value).text("thickness")).changed() {
+                engine.edit_selection_strokes(|s| s.thickness = Number::new(thickness));
+            }
+
+            let mut alpha = stroke.color.alpha;
+            if ui.add(egui::Slider::new(&mut alpha, 0..=255).text("opacity")).changed() {
+                engine.edit_selection_strokes(|s| s.color.alpha = alpha);
+            }
+
+            let mut dash = stroke.dash;
+            if dash_pattern_ui(ui, "selection dash", &mut dash) {
+                engine.edit_selection_strokes(|s| s.dash = dash);
+            }
+            let mut cap = stroke.cap;
+            if line_cap_ui(ui, "selection cap", &mut cap) {
+                engine.edit_selection_strokes(|s| s.cap = cap);
+            }
+            let mut join = stroke.join;
+            if line_join_ui(ui, "selection join", &mut join) {
+                engine.edit_selection_strokes(|s| s.join = join);
+            }
+        }
+
+        if let Some((mut fill, is_common)) = engine.selection_fill() {
+            ui.separator();
+            ui.label(if is_common { "fill" } else { "fill (mixed)" });
+            // unlike the stroke the fill is replaced as a whole, gradients don't have independent parts
+            if fill_ui(ui, "selection fill", &mut fill) {
+                engine.edit_selection_fills(|f| *f = fill.clone());
+            }
+        }
+
+        if let Some((adjustments, is_common)) = engine.selection_adjustments() {
+            ui.separator();
+            ui.label(if is_common { "picture" } else { "picture (mixed)" });
+
+            let mut brightness = adjustments.brightness;
+            if ui.add(egui::Slider::new(&mut brightness, -1.0..=1.0).text("brightness")).changed() {
+                engine.edit_selection_adjustments(|a| a.brightness = brightness);
+            }
+            let mut contrast = adjustments.contrast;
+            if ui.add(egui::Slider::new(&mut contrast, -1.0..=1.0).text("contrast")).changed() {
+                engine.edit_selection_adjustments(|a| a.contrast = contrast);
+            }
+            let mut saturation = adjustments.saturation;
+            if ui.add_enabled(!adjustments.grayscale, egui::Slider::new(&mut saturation, -1.0..=1.0).text("saturation")).changed() {
+                engine.edit_selection_adjustments(|a| a.saturation = saturation);
+            }
+            let mut opacity = adjustments.opacity;
+            if ui.add(egui::Slider::new(&mut opacity, 0.0..=1.0).text("opacity")).changed() {
+                engine.edit_selection_adjustments(|a| a.opacity = opacity);
+            }
+            ui.horizontal(|ui| {
+                let mut grayscale = adjustments.grayscale;
+                if ui.checkbox(&mut grayscale, "grayscale").changed() {
+                    engine.edit_selection_adjustments(|a| a.grayscale = grayscale);
+                }
+                let mut invert = adjustments.invert;
+                if ui.checkbox(&mut invert, "invert").changed() {
+                    engine.edit_selection_adjustments(|a| a.invert = invert);
+                }
+                if ui.add_enabled(!adjustments.is_identity() || !is_common, egui::Button::new("reset")).clicked() {
+                    engine.edit_selection_adjustments(|a| *a = ImageAdjustments::default());
+                }
+            });
+        }
+
+        if let Some((playback, is_common)) = engine.selection_playback() {
+            ui.separator();
+            ui.label(if is_common { "animation" } else { "animation (mixed)" });
+            ui.horizontal(|ui| {
+                if ui.button(if playback.is_playing { "pause" } else { "play" }).clicked() {
+                    let is_playing = !playback.is_playing;
+                    engine.edit_selection_playback(|p| p.is_playing = is_playing);
+                }
+                let mut frame = playback.frame;
+                // picking a frame freezes the animation on it
+                let slider = egui::Slider::new(&mut frame, 0..=playback.frame_count - 1).text("frame");
+                if ui.add(slider).on_hover_text("the animation stays on the chosen frame until it's played again").changed() {
+                    engine.edit_selection_playback(|p| {
+                        p.frame = frame;
+                        p.is_playing = false;
+                    });
+                }
+            });
+        }
+
+        let file_links = engine.selection_file_links();
+        if !file_links.is_empty() {
+            ui.separator();
+            ui.label("files");
+        }
+        for (i, link) in file_links.iter().enumerate() {
+            let name = link.path.file_name().map_or_else(|| link.path.display().to_string(), |name| name.to_string_lossy().into_owned());
+            ui.label(name).on_hover_text(link.path.display().to_string());
+            ui.horizontal(|ui| {
+                let mut is_linked = link.is_linked;
+                // a missing file has no data that could be embedded
+                let checkbox = ui.add_enabled(!link.is_missing, egui::Checkbox::new(&mut is_linked, "linked")).on_hover_text(
+                    "linked: only the path is stored, the file is reloaded when it changes\nembedded: a copy is stored in the drawing"
+                );
+                if checkbox.changed() {
+                    engine.edit_selection_file_link(i, |l| l.is_linked = is_linked);
+                }
+                if link.is_missing {
+                    ui.colored_label(ui.visuals().error_fg_color, "missing");
+                }
+                if ui.button("relink...").on_hover_text("load the picture from another file").clicked() {
+                    if let Some(path) = path_from_open_file_dialog() {
+                        engine.edit_selection_file_link(i, |l| l.path = path);
+                    }
+                }
+            });
+        }
+
+        ui.separator();
+        let mut x      = selection_rect.p1.x;
+        let mut y      = selection_rect.p1.y;
+        let mut width  = selection_rect.width().value;
+        let mut height = selection_rect.height().value;
+        let mut changed = false;
+        egui::Grid::new("geometry").num_columns(2).show(ui, |ui| {
+            ui.label("x");
+            changed |= ui.add(egui::DragValue::new(&mut x)).changed();
+            ui.end_row();
+            ui.label("y");
+            changed |= ui.add(egui::DragValue::new(&mut y)).changed();
+            ui.end_row();
+            ui.label("width");
+            changed |= ui.add(egui::DragValue::new(&mut width).range(1.0..=f32::INFINITY).clamp_existing_to_range(false)).changed();
+            ui.end_row();
+            ui.label("height");
+            changed |= ui.add(egui::DragValue::new(&mut height).range(1.0..=f32::INFINITY).clamp_existing_to_range(false)).changed();
+            ui.end_row();
+        });
+        if changed {
+            engine.set_selection_rect(Rectangle::from_point_and_size(Vector2::new(x, y), Number::new(width), Number::new(height)));
+        }
+    });
+}
+
+
+// The editors below are shared with the toolbar, they return whether the value was changed.
+
+pub fn dash_pattern_ui(ui: &mut egui::Ui, id_salt: &str, dash: &mut DashPattern<WorldSpace>) -> bool {
+    let mut changed = false;
+    let patterns = [
+        (DashPattern::Solid,  "solid"),
+        (DashPattern::Dashed, "dashed"),
+        (DashPattern::Dotted, "dotted"),
+        (DashPattern::Custom([Number::new(8.0), Number::new(4.0), Number::new(2.0), Number::new(4.0)]), "custom"),
+    ];
+    let selected_text = patterns.iter().find(|(pattern, _)| std::mem::discriminant(pattern) == std::mem::discriminant(dash)).map_or("", |(_, label)| label);
+    egui::ComboBox::new(id_salt, "dash").selected_text(selected_text).show_ui(ui, |ui| {
+        for (pattern, label) in patterns {
+            let is_selected = std::mem::discriminant(&pattern) == std::mem::discriminant(dash);
+            if ui.selectable_label(is_selected, label).clicked() && !is_selected {
+                *dash = pattern;
+                changed = true;
+            }
+        }
+    });
+
+    if let DashPattern::Custom(lengths) = dash {
+        ui.horizontal(|ui| {
+            for (i, length) in lengths.iter_mut().enumerate() {
+                let hint = if i % 2 == 0 { "dash length" } else { "gap length" };
+                changed |= ui.add(egui::DragValue::new(&mut length.value).range(0.0..=1000.0).speed(0.5)).on_hover_text(hint).changed();
+            }
+        });
+    }
+
+    changed
+}
+
+
+pub fn line_cap_ui(ui: &mut egui::Ui, id_salt: &str, cap: &mut LineCap) -> bool {
+    let caps = [(LineCap::Butt, "butt"), (LineCap::Round, "round"), (LineCap::Square, "square")];
+    combo_box(ui, id_salt, "cap", caps, cap)
+}
+
+
+pub fn line_join_ui(ui: &mut egui::Ui, id_salt: &str, join: &mut LineJoin) -> bool {
+    let joins = [(LineJoin::Miter, "miter"), (LineJoin::Round, "round"), (LineJoin::Bevel, "bevel")];
+    combo_box(ui, id_salt, "join", joins, join)
+}
+
+
+pub fn fill_ui(ui: &mut egui::Ui, id_salt: &str, fill: &mut Fill) -> bool {
+    let mut changed = false;
+    let current_color = match fill {
+        Fill::None                                                      => Color::from_rgb(255, 255, 255),
+        Fill::Solid(color)                                              => *color,
+        Fill::LinearGradient(gradient) | Fill::RadialGradient(gradient) => gradient.color_at_offset(0.0),
+    };
+    let stops = fill.gradient().map(|gradient| gradient.stops.clone())
+        .unwrap_or_else(|| Gradient::new(Vector2::zero(), Vector2::zero(), current_color, Color::from_rgb(255, 255, 255)).stops);
+    let kinds = [
+        (Fill::None,                                                                                                          "none"),
+        (Fill::Solid(current_color),                                                                                          "solid"),
+        (Fill::LinearGradient(Gradient { start: Vector2::new(0.0, 0.5), end: Vector2::new(1.0, 0.5), stops: stops.clone() }), "linear gradient"),
+        (Fill::RadialGradient(Gradient { start: Vector2::new(0.5, 0.5), end: Vector2::new(1.0, 0.5), stops }),                "radial gradient"),
+    ];
+    let selected_text = kinds.iter().find(|(kind, _)| std::mem::discriminant(kind) == std::mem::discriminant(fill)).map_or("", |(_, label)| label);
+    egui::ComboBox::new(id_salt, "type").selected_text(selected_text).show_ui(ui, |ui| {
+        for (kind, label) in kinds {
+            let is_selected = std::mem::discriminant(&kind) == std::mem::discriminant(fill);
+            if ui.selectable_label(is_selected, label).clicked() && !is_selected {
+                *fill = kind;
+                changed = true;
+            }
+        }
+    });
+
+    match fill {
+        Fill::None => {},
+        Fill::Solid(color) => {
+            changed |= color_ui(ui, color);
+        },
+        Fill::LinearGradient(gradient) | Fill::RadialGradient(gradient) => {
+            let mut to_be_removed = None;
+            for (i, stop) in gradient.stops.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    changed |= color_ui(ui, &mut stop.color);
+                    changed |= ui.add(egui::DragValue::new(&mut stop.offset).range(0.0..=1.0).speed(0.01)).on_hover_text("position of the stop").changed();
+                    if ui.small_button("x").on_hover_text("remove the stop").clicked() {
+                        to_be_removed = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = to_be_removed.filter(|_| gradient.stops.len() > 2) {
+                gradient.stops.remove(i);
+                changed = true;
+            }
+            if ui.small_button("add stop").clicked() {
+                // in the middle of the widest gap between two stops
+                let offset = gradient.stops.windows(2)
+                    .max_by(|a, b| (a[1].offset - a[0].offset).total_cmp(&(b[1].offset - b[0].offset)))
+                    .map_or(0.5, |s1s2| (s1s2[0].offset + s1s2[1].offset) * 0.5);
+                gradient.stops.push(GradientStop { offset, color: gradient.color_at_offset(offset) });
+                changed = true;
+            }
+            if changed {
+                gradient.sort_stops();
+            }
+            ui.label("drag the handles on the canvas to move the gradient");
+        },
+    }
+
+    changed
+}
+
+
+fn color_ui(ui: &mut egui::Ui, color: &mut Color) -> bool {
+    let mut rgba = [color.red, color.green, color.blue, color.alpha];
+    let changed = ui.color_edit_button_srgba_unmultiplied(&mut rgba).changed();
+    if changed {
+        let [red, green, blue, alpha] = rgba;
+        *color = Color { red, green, blue, alpha };
+    }
+    changed
+}
+
+
+fn combo_box<T: Copy + PartialEq, const N: usize>(ui: &mut egui::Ui, id_salt: &str, label: &str, options: [(T, &str); N], value: &mut T) -> bool {
+    let mut changed = false;
+    let selected_text = options.iter().find(|(option, _)| option == value).map_or("", |(_, text)| text);
+    egui::ComboBox::new(id_salt, label).selected_text(selected_text).show_ui(ui, |ui| {
+        for (option, text) in options {
+            if ui.selectable_label(option == *value, text).clicked() && option != *value {
+                *value = option;
+                changed = true;
+            }
+        }
+    });
+    changed
+}
@@ -0,0 +1,191 @@
+use std::sync::Arc;
+use image::{imageops, RgbaImage};
+use crate::primitives::*;
+use crate::engine::ScreenPainter;
+
+
+type Point = Vector2<ScreenSpace>;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FillRule {
+    NonZero,
+    EvenOdd,
+}
+
+
+// Draws into an image with a transparent background instead of onto the screen, e.g. for copying the selection as a PNG.
+// Shapes are filled scanline by scanline, every pixel row is sampled several times to smooth the edges.
+pub struct RasterPainter {
+    pub image: RgbaImage,
+}
+
+impl RasterPainter {
+    const SAMPLES_PER_ROW: usize = 4;
+
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            image: RgbaImage::new(width, height),
+        }
+    }
+
+    // Fills the area enclosed by `paths`, every path is closed implicitly. `paint` gives the color at the center of a pixel.
+    fn fill_paths(&mut self, paths: &[Vec<Point>], rule: FillRule, paint: impl Fn(Point) -> Color) {
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for p in paths.iter().flatten() {
+            min_x = min_x.min(p.x);
+            min_y = min_y.min(p.y);
+            max_x = max_x.max(p.x);
+            max_y = max_y.max(p.y);
+        }
+        let x_start = min_x.floor().max(0.0) as u32;
+        let x_end = max_x.ceil().min(self.image.width() as f32) as u32;
+        let y_start = min_y.floor().max(0.0) as u32;
+        let y_end = max_y.ceil().min(self.image.height() as f32) as u32;
+        if x_start >= x_end || y_start >= y_end {
+            return;
+        }
+
+        // horizontal edges never cross a scanline
+        let edges = paths.iter().flat_map(|path| {
+            (0..path.len()).map(|i| (path[i], path[(i + 1) % path.len()]))
+        }).filter(|(a, b)| a.y != b.y).collect::<Vec<_>>();
+
+        let mut coverage = vec![0.0; (x_end - x_start) as usize];
+        let mut crossings: Vec<(f32, i32)> = Vec::new();
+        let weight = 1.0 / Self::SAMPLES_PER_ROW as f32;
+        for y in y_start..y_end {
+            coverage.fill(0.0);
+            for sample in 0..Self::SAMPLES_PER_ROW {
+                let sample_y = y as f32 + (sample as f32 + 0.5) * weight;
+                crossings.clear();
+                for (a, b) in edges.iter() {
+                    if (a.y <= sample_y) != (b.y <= sample_y) {
+                        let x = a.x + (sample_y - a.y) / (b.y - a.y) * (b.x - a.x);
+                        crossings.push((x - x_start as f32, if b.y > a.y { 1 } else { -1 }));
+                    }
+                }
+                crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+                let mut winding = 0;
+                for i in 0..crossings.len().saturating_sub(1) {
+                    winding += crossings[i].1;
+                    let is_inside = match rule {
+                        FillRule::NonZero => winding != 0,
+                        FillRule::EvenOdd => winding % 2 != 0,
+                    };
+                    if is_inside {
+                        add_span(&mut coverage, crossings[i].0, crossings[i + 1].0, weight);
+                    }
+                }
+            }
+
+            for (i, c) in coverage.iter().enumerate() {
+                if *c > 0.0 {
+                    let x = x_start + i as u32;
+                    let color = paint(Point::new(x as f32 + 0.5, y as f32 + 0.5));
+                    self.blend(x, y, Color { alpha: (color.alpha as f32 * c.min(1.0)).round() as u8, ..color });
+                }
+            }
+        }
+    }
+
+    fn blend(&mut self, x: u32, y: u32, color: Color) {
+        let pixel = self.image.get_pixel_mut(x, y);
+        let [red, green, blue, alpha] = pixel.0;
+        let result = color.over(Color { red, green, blue, alpha });
+        pixel.0 = [result.red, result.green, result.blue, result.alpha];
+    }
+}
+
+impl ScreenPainter for RasterPainter {
+    type Texture = Arc<image::DynamicImage>;
+
+    fn draw_line(&mut self, start: Point, end: Point, stroke: Stroke<ScreenSpace>) {
+        self.draw_polyline(&[start, end], stroke);
+    }
+
+    fn draw_polyline(&mut self, points: &[Point], stroke: Stroke<ScreenSpace>) {
+        let paths = stroke_outline(points, stroke);
+        self.fill_paths(&paths, FillRule::NonZero, |_| stroke.color);
+    }
+
+    fn draw_circle(&mut self, center: Point, radius: Number<ScreenSpace>, stroke: Stroke<ScreenSpace>) {
+        self.fill_paths(&[circle(center, radius.value)], FillRule::NonZero, |_| stroke.color);
+    }
+
+    fn draw_rectangle(&mut self, rectangle: Rectangle<ScreenSpace>, stroke: Stroke<ScreenSpace>) {
+        let rect = Rectangle::from_points_well_ordered(rectangle.p1, rectangle.p2);
+        let half_thickness = stroke.thickness * 0.5;
+        let mut paths = vec![rect.expanded_by(half_thickness).vertices().to_vec()];
+        if rect.width() > stroke.thickness && rect.height() > stroke.thickness {
+            paths.push(rect.expanded_by(half_thickness * -1.0).vertices().to_vec());
+        }
+        self.fill_paths(&paths, FillRule::EvenOdd, |_| stroke.color);
+    }
+
+    fn draw_rectangle_filled(&mut self, rectangle: Rectangle<ScreenSpace>, color: Color, stroke: Option<Stroke<ScreenSpace>>) {
+        self.fill_paths(&[rectangle.vertices().to_vec()], FillRule::NonZero, |_| color);
+        if let Some(stroke) = stroke {
+            self.draw_rectangle(rectangle, stroke);
+        }
+    }
+
+    fn draw_polygon_filled(&mut self, points: &[Point], fill: &Fill, frame: Rectangle<ScreenSpace>) {
+        if *fill == Fill::None {
+            return;
+        }
+        let transparent = Color { red: 0, green: 0, blue: 0, alpha: 0 };
+        self.fill_paths(&[points.to_vec()], FillRule::EvenOdd, |p| fill.color_at(frame.point_to_unit(p)).unwrap_or(transparent));
+    }
+
+    fn load_image(&mut self, _name: &str, image: &Arc<image::DynamicImage>) -> Self::Texture {
+        Arc::clone(image)
+    }
+
+    fn draw_image(&mut self, frame: Rectangle<ScreenSpace>, texture: &Self::Texture, flip: Flip) {
+        let frame = Rectangle::from_points_well_ordered(frame.p1, frame.p2);
+        let (left, top) = (frame.p1.x.round(), frame.p1.y.round());
+        let width = frame.p2.x.round() - left;
+        let height = frame.p2.y.round() - top;
+        if width < 1.0 || height < 1.0 {
+            return;
+        }
+
+        let mut scaled = texture.resize_exact(width as u32, height as u32, imageops::FilterType::Triangle).to_rgba8();
+        if flip.horizontal {
+            imageops::flip_horizontal_in_place(&mut scaled);
+        }
+        if flip.vertical {
+            imageops::flip_vertical_in_place(&mut scaled);
+        }
+        for (x, y, pixel) in scaled.enumerate_pixels() {
+            let (target_x, target_y) = (left as i64 + x as i64, top as i64 + y as i64);
+            if (0..self.image.width() as i64).contains(&target_x) && (0..self.image.height() as i64).contains(&target_y) {
+                let [red, green, blue, alpha] = pixel.0;
+                self.blend(target_x as u32, target_y as u32, Color { red, green, blue, alpha });
+            }
+        }
+    }
+}
+
+
+// Adds `weight` to the pixels between `start` and `end`, the pixels at the ends get the part of it that they are covered.
+fn add_span(coverage: &mut [f32], start: f32, end: f32, weight: f32) {
+    let start = start.max(0.0);
+    let end = end.min(coverage.len() as f32);
+    if start >= end {
+        return;
+    }
+    let first = start as usize;
+    let last = end.ceil() as usize - 1;
+    if first == last {
+        coverage[first] += (end - start) * weight;
+        return;
+    }
+    coverage[first] += (first as f32 + 1.0 - start) * weight;
+    for c in coverage[first + 1..last].iter_mut() {
+        *c += weight;
+    }
+    coverage[last] += (end - last as f32) * weight;
+}
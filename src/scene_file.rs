@@ -0,0 +1,496 @@
+use std::sync::Arc;
+use crate::primitives::*;
+use crate::engine::PaintObject;
+use crate::egui_painter::EguiPainter;
+use crate::error::Error;
+use crate::paint_object::{freehand_curve::FreehandCurve, straight_line::StraightLine, picture::Picture, shape::Shape};
+
+
+// Text format for saving the paint objects, one object per line.
+// The first token of a line says what kind of object it is, the rest is up to the object.
+// Images are only encoded when the writer is finished, so that it can happen on a background thread.
+const HEADER: &str = "picipaint-scene 1";
+
+
+enum Part {
+    Text(String),
+    Image(Arc<image::DynamicImage>),
+}
+
+
+impl PartialEq for Part {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Part::Text(a),  Part::Text(b))  => a == b,
+            // images are never modified in place, so the same allocation means the same image
+            (Part::Image(a), Part::Image(b)) => Arc::ptr_eq(a, b),
+            _                                => false,
+        }
+    }
+}
+
+
+// Comparing two writers is cheap, it can be used to decide whether the scene has to be saved again.
+#[derive(PartialEq)]
+pub struct SceneWriter {
+    parts: Vec<Part>,
+    object_count: usize,
+}
+
+impl SceneWriter {
+    pub fn new() -> Self {
+        Self {
+            parts: vec![Part::Text(String::from(HEADER))],
+            object_count: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.object_count == 0
+    }
+
+    pub fn begin_object(&mut self, kind: &str) {
+        self.object_count += 1;
+        self.push_token(&format!("\n{}", kind));
+    }
+
+    // names an optional part at the end of a line, see `SceneReader::has_more`
+    pub fn keyword(&mut self, keyword: &str) {
+        self.push_token(&format!(" {}", keyword));
+    }
+
+    pub fn number(&mut self, x: f32) {
+        self.push_token(&format!(" {}", x));
+    }
+
+    pub fn count(&mut self, n: usize) {
+        self.push_token(&format!(" {}", n));
+    }
+
+    pub fn bool(&mut self, b: bool) {
+        self.push_token(if b { " 1" } else { " 0" });
+    }
+
+    pub fn point<T: Tag>(&mut self, p: Vector2<T>) {
+        self.number(p.x);
+        self.number(p.y);
+    }
+
+    pub fn rect<T: Tag>(&mut self, rect: Rectangle<T>) {
+        self.point(rect.p1);
+        self.point(rect.p2);
+    }
+
+    pub fn color(&mut self, color: Color) {
+        // always with alpha, so that it's one token
+        self.push_token(&format!(" #{:02x}{:02x}{:02x}{:02x}", color.red, color.green, color.blue, color.alpha));
+    }
+
+    // base64 with a prefix, so that it can't contain whitespace and isn't empty
+    pub fn text(&mut self, text: &str) {
+        self.push_token(&format!(" s{}", base64_encode(text.as_bytes())));
+    }
+
+    pub fn image(&mut self, image: &Arc<image::DynamicImage>) {
+        self.push_token(" ");
+        self.parts.push(Part::Image(Arc::clone(image)));
+    }
+
+    pub fn stroke(&mut self, stroke: Stroke<WorldSpace>) {
+        self.color(stroke.color);
+        self.number(stroke.thickness.value);
+        match stroke.dash {
+            DashPattern::Solid           => self.push_token(" solid"),
+            DashPattern::Dashed          => self.push_token(" dashed"),
+            DashPattern::Dotted          => self.push_token(" dotted"),
+            DashPattern::Custom(lengths) => {
+                self.push_token(" custom");
+                for length in lengths {
+                    self.number(length.value);
+                }
+            },
+        }
+        self.push_token(match stroke.cap {
+            LineCap::Butt   => " butt",
+            LineCap::Round  => " round",
+            LineCap::Square => " square",
+        });
+        self.push_token(match stroke.join {
+            LineJoin::Miter => " miter",
+            LineJoin::Round => " round",
+            LineJoin::Bevel => " bevel",
+        });
+    }
+
+    pub fn fill(&mut self, fill: &Fill) {
+        let gradient = match fill {
+            Fill::None                     => { self.push_token(" none"); None },
+            Fill::Solid(color)             => { self.push_token(" solid"); self.color(*color); None },
+            Fill::LinearGradient(gradient) => { self.push_token(" linear"); Some(gradient) },
+            Fill::RadialGradient(gradient) => { self.push_token(" radial"); Some(gradient) },
+        };
+        if let Some(gradient) = gradient {
+            self.point(gradient.start);
+            self.point(gradient.end);
+            self.count(gradient.stops.len());
+            for stop in gradient.stops.iter() {
+                self.number(stop.offset);
+                self.color(stop.color);
+            }
+        }
+    }
+
+    pub fn adjustments(&mut self, adjustments: ImageAdjustments) {
+        self.number(adjustments.brightness);
+        self.number(adjustments.contrast);
+        self.number(adjustments.saturation);
+        self.bool(adjustments.grayscale);
+        self.bool(adjustments.invert);
+        self.number(adjustments.opacity);
+    }
+
+    // Encodes the images, this is the slow part.
+    pub fn finish(&self) -> Result<String, Error> {
+        let mut result = String::new();
+        for part in self.parts.iter() {
+            match part {
+                Part::Text(text)   => result += text,
+                Part::Image(image) => {
+                    let mut png = std::io::Cursor::new(Vec::new());
+                    image.write_to(&mut png, image::ImageFormat::Png).map_err(|err| Error::image(None, err))?;
+                    result += "i";
+                    result += &base64_encode(png.get_ref());
+                },
+            }
+        }
+        result += "\n";
+        Ok(result)
+    }
+
+    fn push_token(&mut self, token: &str) {
+        match self.parts.last_mut() {
+            Some(Part::Text(text)) => text.push_str(token),
+            _                      => self.parts.push(Part::Text(String::from(token))),
+        }
+    }
+}
+
+
+pub struct SceneReader<'a> {
+    tokens: std::str::SplitWhitespace<'a>,
+    line_number: usize,
+}
+
+impl<'a> SceneReader<'a> {
+    pub fn keyword(&mut self) -> Result<&'a str, Error> {
+        self.token()
+    }
+
+    pub fn number(&mut self) -> Result<f32, Error> {
+        let token = self.token()?;
+        token.parse::<f32>().ok().filter(|x| x.is_finite()).ok_or_else(|| self.error(&format!("expected a number, found \"{}\"", token)))
+    }
+
+    pub fn count(&mut self) -> Result<usize, Error> {
+        let token = self.token()?;
+        token.parse::<usize>().map_err(|_| self.error(&format!("expected a count, found \"{}\"", token)))
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Error> {
+        match self.token()? {
+            "0" => Ok(false),
+            "1" => Ok(true),
+            token => Err(self.error(&format!("expected 0 or 1, found \"{}\"", token))),
+        }
+    }
+
+    pub fn point<T: Tag>(&mut self) -> Result<Vector2<T>, Error> {
+        Ok(Vector2::new(self.number()?, self.number()?))
+    }
+
+    pub fn rect<T: Tag>(&mut self) -> Result<Rectangle<T>, Error> {
+        Ok(Rectangle { p1: self.point()?, p2: self.point()? })
+    }
+
+    pub fn color(&mut self) -> Result<Color, Error> {
+        let token = self.token()?;
+        Color::from_hex(token).ok_or_else(|| self.error(&format!("expected a color, found \"{}\"", token)))
+    }
+
+    pub fn text(&mut self) -> Result<String, Error> {
+        let token = self.token()?;
+        token.strip_prefix('s')
+             .and_then(base64_decode)
+             .and_then(|bytes| String::from_utf8(bytes).ok())
+             .ok_or_else(|| self.error("invalid text"))
+    }
+
+    pub fn image(&mut self) -> Result<image::DynamicImage, Error> {
+        let token = self.token()?;
+        let bytes = token.strip_prefix('i').and_then(base64_decode).ok_or_else(|| self.error("invalid image data"))?;
+        image::load_from_memory_with_format(&bytes, image::ImageFormat::Png).map_err(|err| Error::image(None, err))
+    }
+
+    pub fn stroke(&mut self) -> Result<Stroke<WorldSpace>, Error> {
+        let mut stroke = Stroke::new(self.color()?, Number::new(self.number()?));
+        stroke.dash = match self.token()? {
+            "solid"  => DashPattern::Solid,
+            "dashed" => DashPattern::Dashed,
+            "dotted" => DashPattern::Dotted,
+            "custom" => DashPattern::Custom([Number::new(self.number()?), Number::new(self.number()?), Number::new(self.number()?), Number::new(self.number()?)]),
+            token    => return Err(self.error(&format!("unknown dash pattern \"{}\"", token))),
+        };
+        stroke.cap = match self.token()? {
+            "butt"   => LineCap::Butt,
+            "round"  => LineCap::Round,
+            "square" => LineCap::Square,
+            token    => return Err(self.error(&format!("unknown line cap \"{}\"", token))),
+        };
+        stroke.join = match self.token()? {
+            "miter" => LineJoin::Miter,
+            "round" => LineJoin::Round,
+            "bevel" => LineJoin::Bevel,
+            token   => return Err(self.error(&format!("unknown line join \"{}\"", token))),
+        };
+        Ok(stroke)
+    }
+
+    pub fn fill(&mut self) -> Result<Fill, Error> {
+        let kind = self.token()?;
+        let mut gradient = || -> Result<Gradient, Error> {
+            let start = self.point()?;
+            let end = self.point()?;
+            let stop_count = self.count()?;
+            let mut stops = Vec::new();
+            for _ in 0..stop_count {
+                stops.push(GradientStop { offset: self.number()?, color: self.color()? });
+            }
+            let mut gradient = Gradient { start, end, stops };
+            gradient.sort_stops();
+            Ok(gradient)
+        };
+        match kind {
+            "none"   => Ok(Fill::None),
+            "solid"  => Ok(Fill::Solid(self.color()?)),
+            "linear" => Ok(Fill::LinearGradient(gradient()?)),
+            "radial" => Ok(Fill::RadialGradient(gradient()?)),
+            token    => Err(self.error(&format!("unknown fill \"{}\"", token))),
+        }
+    }
+
+    // for values that were added to the end of a line in later versions
+    pub fn has_more(&self) -> bool {
+        self.peek().is_some()
+    }
+
+    // the next token, without reading it
+    pub fn peek(&self) -> Option<&'a str> {
+        self.tokens.clone().next()
+    }
+
+    pub fn adjustments(&mut self) -> Result<ImageAdjustments, Error> {
+        Ok(ImageAdjustments {
+            brightness: self.number()?,
+            contrast: self.number()?,
+            saturation: self.number()?,
+            grayscale: self.bool()?,
+            invert: self.bool()?,
+            opacity: self.number()?,
+        })
+    }
+
+    pub fn error(&self, message: &str) -> Error {
+        Error::InvalidScene { line: self.line_number, message: String::from(message) }
+    }
+
+    fn token(&mut self) -> Result<&'a str, Error> {
+        self.tokens.next().ok_or_else(|| self.error("unexpected end of line"))
+    }
+}
+
+
+pub fn load_objects(text: &str) -> Result<Vec<Box<dyn PaintObject<EguiPainter>>>, Error> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some(HEADER) {
+        return Err(Error::InvalidScene { line: 1, message: String::from("not a scene file, or it was saved by an incompatible version") });
+    }
+
+    let mut objects: Vec<Box<dyn PaintObject<EguiPainter>>> = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let mut tokens = line.split_whitespace();
+        let Some(kind) = tokens.next() else {
+            continue;
+        };
+        let mut reader = SceneReader { tokens, line_number: i + 2 };
+        objects.push(match kind {
+            FreehandCurve::KIND  => Box::new(FreehandCurve::load(&mut reader)?),
+            StraightLine::KIND   => Box::new(StraightLine::load(&mut reader)?),
+            Picture::KIND        => Box::new(Picture::load(&mut reader)?),
+            Picture::LINKED_KIND => Box::new(Picture::load_linked(&mut reader)?),
+            Shape::KIND          => Box::new(Shape::load(&mut reader)?),
+            _                    => return Err(reader.error(&format!("unknown kind of object \"{}\"", kind))),
+        });
+    }
+    Ok(objects)
+}
+
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            }
+            else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut result = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = BASE64_ALPHABET.iter().position(|a| *a == c)? as u32;
+        buffer = buffer << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(result)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // loads the scene and saves it again
+    fn resave(text: &str) -> String {
+        let mut writer = SceneWriter::new();
+        for object in load_objects(text).unwrap() {
+            object.save(&mut writer);
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn objects_round_trip() {
+        let text = [
+            HEADER,
+            "line 0 0 10 5.5 #ff0000ff 2 solid butt miter",
+            "curve 0 0 4 4 #00000080 1.5 custom 1 2 3 4 round bevel 3 0 0 2 4 4 0",
+            "shape 2 0 0 10 10 #000000ff 1 dashed square round linear 0 0 1 1 2 0 #ff0000ff 1 #0000ffff 3 0 0 10 0 5 10",
+            "shape 1 -5 -5 5 5 #000000ff 1 dotted butt miter radial 0.5 0.5 1 0.5 1 0.25 #00ff0040 0",
+            "shape 0 -5 -5 5 5 #000000ff 1 solid butt miter none 0",
+            "",
+        ].join("\n");
+        assert_eq!(resave(&text), text);
+    }
+
+    #[test]
+    fn pictures_round_trip() {
+        let image = Arc::new(image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(3, 2, |x, y| image::Rgba([x as u8 * 80, y as u8 * 200, 7, 255]))));
+        let mut writer = SceneWriter::new();
+        writer.begin_object(Picture::KIND);
+        writer.rect(Rectangle::<WorldSpace> { p1: Vector2::new(1.0, 2.0), p2: Vector2::new(31.0, 22.0) });
+        writer.bool(true);
+        writer.bool(false);
+        writer.text("a picture.png");
+        writer.image(&image);
+        writer.keyword("path");
+        writer.text("/pictures/a picture.png");
+        writer.keyword("rotate");
+        writer.count(3);
+        writer.begin_object(Picture::LINKED_KIND);
+        writer.rect(Rectangle::<WorldSpace> { p1: Vector2::new(0.0, 0.0), p2: Vector2::new(10.0, 10.0) });
+        writer.bool(false);
+        writer.bool(false);
+        writer.text("/pictures/this file does not exist.png");
+        let text = writer.finish().unwrap();
+        assert_eq!(resave(&text), text);
+    }
+
+    #[test]
+    fn bare_picture_paths_are_still_read() {
+        let image = Arc::new(image::DynamicImage::ImageRgba8(image::RgbaImage::new(2, 2)));
+        let mut legacy = SceneWriter::new();
+        let mut current = SceneWriter::new();
+        for writer in [&mut legacy, &mut current] {
+            writer.begin_object(Picture::KIND);
+            writer.rect(Rectangle::<WorldSpace> { p1: Vector2::new(0.0, 0.0), p2: Vector2::new(2.0, 2.0) });
+            writer.bool(false);
+            writer.bool(false);
+            writer.text("name");
+            writer.image(&image);
+        }
+        legacy.text("/pictures/old.png");
+        current.keyword("path");
+        current.text("/pictures/old.png");
+        assert_eq!(resave(&legacy.finish().unwrap()), current.finish().unwrap());
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        let stroke = Stroke { color: Color { red: 1, green: 2, blue: 3, alpha: 4 }, thickness: Number::new(0.25), dash: DashPattern::Dotted, cap: LineCap::Square, join: LineJoin::Bevel };
+        let adjustments = ImageAdjustments { brightness: 0.5, contrast: -0.25, saturation: 1.0, grayscale: true, invert: false, opacity: 0.75 };
+        let texts = ["", "a", "with spaces and\nnew lines", "ünïcödé ✓"];
+
+        let mut writer = SceneWriter::new();
+        writer.begin_object("test");
+        writer.stroke(stroke);
+        writer.adjustments(adjustments);
+        for text in texts {
+            writer.text(text);
+        }
+        writer.count(42);
+        let output = writer.finish().unwrap();
+        let line = output.lines().nth(1).unwrap();
+
+        let mut reader = SceneReader { tokens: line.split_whitespace(), line_number: 2 };
+        assert_eq!(reader.keyword().unwrap(), "test");
+        assert_eq!(reader.stroke().unwrap(), stroke);
+        assert_eq!(reader.adjustments().unwrap(), adjustments);
+        for text in texts {
+            assert_eq!(reader.text().unwrap(), text);
+        }
+        assert!(reader.has_more());
+        assert_eq!(reader.count().unwrap(), 42);
+        assert!(!reader.has_more());
+        assert!(matches!(reader.number(), Err(Error::InvalidScene { line: 2, .. })));
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        let bytes = (0..=255).collect::<Vec<u8>>();
+        for length in 0..8 {
+            assert_eq!(base64_decode(&base64_encode(&bytes[250 - length..250])), Some(bytes[250 - length..250].to_vec()));
+        }
+        assert_eq!(base64_encode(b"pici"), "cGljaQ==");
+        assert_eq!(base64_decode(&base64_encode(&bytes)), Some(bytes));
+        assert_eq!(base64_decode("not base64!"), None);
+    }
+
+    #[test]
+    fn invalid_scenes_are_rejected() {
+        assert!(matches!(load_objects("picipaint-scene 2\n"), Err(Error::InvalidScene { line: 1, .. })));
+        assert!(matches!(load_objects(&format!("{}\n\nline 0 0 1 1 #000000ff 1 solid butt miter\nblob 1 2\n", HEADER)), Err(Error::InvalidScene { line: 4, .. })));
+        assert!(matches!(load_objects(&format!("{}\nline 0 0 1\n", HEADER)), Err(Error::InvalidScene { line: 2, .. })));
+        assert!(matches!(load_objects(&format!("{}\nshape 2 0 0 1 1 #000000ff 1 solid butt miter none 2 0 0 1 1\n", HEADER)), Err(Error::InvalidScene { line: 2, .. })));
+        assert_eq!(load_objects(&format!("{}\n\n", HEADER)).unwrap().len(), 0);
+    }
+}
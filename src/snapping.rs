@@ -0,0 +1,139 @@
+use crate::primitives::*;
+
+
+pub struct Snapping {
+    pub grid_is_visible: bool,
+    pub grid_spacing: Number<WorldSpace>,
+    pub snap_to_grid: bool,
+    pub snap_to_objects: bool,
+}
+
+impl Default for Snapping {
+    fn default() -> Self {
+        Self {
+            grid_is_visible: false,
+            grid_spacing: Number::new(50.0),
+            snap_to_grid: false,
+            snap_to_objects: true,
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SnapIndicator {
+    Point(Vector2<WorldSpace>),
+    VerticalGuide { x: f32 },
+    HorizontalGuide { y: f32 },
+}
+
+
+pub struct SnapResult {
+    // has to be added to the snapped points to move them to where they snapped
+    pub correction: Vector2<WorldSpace>,
+    pub indicators: Vec<SnapIndicator>,
+}
+
+
+struct AxisSnap {
+    distance: f32,
+    correction: f32,
+    target: f32,
+    to_grid: bool,
+}
+
+impl Snapping {
+    // The x and y coordinates are snapped independently of each other,
+    // so a point can be aligned to the edge of one object and the center of another at the same time.
+    // When several points are given (e.g. the vertices of a dragged object) the one closest to a target wins.
+    // `targets` are the snap points of all the objects that are not being moved.
+    pub fn snap(&self, points: &[Vector2<WorldSpace>], targets: &[Vector2<WorldSpace>], tolerance: Number<WorldSpace>) -> SnapResult {
+        let x = self.snap_axis(points.iter().map(|p| p.x), targets.iter().map(|t| t.x), tolerance);
+        let y = self.snap_axis(points.iter().map(|p| p.y), targets.iter().map(|t| t.y), tolerance);
+
+        let mut indicators = Vec::new();
+        match (&x, &y) {
+            (Some(x), Some(y)) if x.to_grid && y.to_grid => {
+                indicators.push(SnapIndicator::Point(Vector2::new(x.target, y.target)));
+            },
+            _ => {
+                if let Some(x) = x.as_ref().filter(|x| !x.to_grid) {
+                    indicators.push(SnapIndicator::VerticalGuide { x: x.target });
+                }
+                if let Some(y) = y.as_ref().filter(|y| !y.to_grid) {
+                    indicators.push(SnapIndicator::HorizontalGuide { y: y.target });
+                }
+            },
+        }
+
+        SnapResult {
+            correction: Vector2::new(x.map_or(0.0, |x| x.correction), y.map_or(0.0, |y| y.correction)),
+            indicators,
+        }
+    }
+
+    fn snap_axis(&self, coordinates: impl Iterator<Item = f32>, targets: impl Iterator<Item = f32> + Clone, tolerance: Number<WorldSpace>) -> Option<AxisSnap> {
+        let mut best: Option<AxisSnap> = None;
+        let mut consider = |coordinate: f32, target: f32, to_grid: bool| {
+            let distance = (target - coordinate).abs();
+            if distance < tolerance.value && best.as_ref().is_none_or(|b| distance < b.distance) {
+                best = Some(AxisSnap { distance, correction: target - coordinate, target, to_grid });
+            }
+        };
+
+        for coordinate in coordinates {
+            if self.snap_to_grid && self.grid_spacing.value > 0.0 {
+                let spacing = self.grid_spacing.value;
+                consider(coordinate, (coordinate / spacing).round() * spacing, true);
+            }
+            if self.snap_to_objects {
+                for target in targets.clone() {
+                    consider(coordinate, target, false);
+                }
+            }
+        }
+
+        best
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapping(snap_to_grid: bool, snap_to_objects: bool) -> Snapping {
+        Snapping { grid_is_visible: false, grid_spacing: Number::new(10.0), snap_to_grid, snap_to_objects }
+    }
+
+    #[test]
+    fn snaps_to_grid_point() {
+        let result = snapping(true, false).snap(&[Vector2::new(21.0, 38.5)], &[], Number::new(3.0));
+        assert_eq!(result.correction, Vector2::new(-1.0, 1.5));
+        assert_eq!(result.indicators, vec![SnapIndicator::Point(Vector2::new(20.0, 40.0))]);
+    }
+
+    #[test]
+    fn axes_snap_independently() {
+        let targets = [Vector2::new(100.0, 0.0), Vector2::new(0.0, 52.0)];
+        let result = snapping(false, true).snap(&[Vector2::new(98.0, 50.0)], &targets, Number::new(3.0));
+        assert_eq!(result.correction, Vector2::new(2.0, 2.0));
+        assert_eq!(result.indicators, vec![SnapIndicator::VerticalGuide { x: 100.0 }, SnapIndicator::HorizontalGuide { y: 52.0 }]);
+    }
+
+    #[test]
+    fn closest_target_wins() {
+        let targets = [Vector2::new(13.0, 1000.0)];
+        // the object target is closer than the grid line at 10
+        let result = snapping(true, true).snap(&[Vector2::new(12.0, 500.0), Vector2::new(44.5, 500.0)], &targets, Number::new(3.0));
+        assert_eq!(result.correction, Vector2::new(1.0, 0.0));
+        assert_eq!(result.indicators, vec![SnapIndicator::VerticalGuide { x: 13.0 }]);
+    }
+
+    #[test]
+    fn nothing_snaps_outside_of_the_tolerance() {
+        let result = snapping(true, true).snap(&[Vector2::new(15.0, 15.0)], &[Vector2::new(20.0, 20.0)], Number::new(3.0));
+        assert_eq!(result.correction, Vector2::zero());
+        assert!(result.indicators.is_empty());
+    }
+}
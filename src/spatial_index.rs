@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use crate::primitives::*;
+
+
+// Uniform grid over the bounding rectangles of the paint objects.
+// Objects are identified by their index in `Engine::objects`,
+// so every change to that vector has to be mirrored here.
+pub struct SpatialIndex {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    rects: Vec<Rectangle<WorldSpace>>,
+    // Objects that would cover too many cells (or whose bounding rectangle is not finite)
+    // are not put in the grid, they are checked one by one on every query instead.
+    oversized: Vec<usize>,
+}
+
+impl SpatialIndex {
+    const MAX_CELLS_PER_OBJECT: i64 = 1024;
+
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            rects: Vec::new(),
+            oversized: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, rect: Rectangle<WorldSpace>) {
+        let id = self.rects.len();
+        self.rects.push(Rectangle::from_points_well_ordered(rect.p1, rect.p2));
+        self.link(id);
+    }
+
+    pub fn update(&mut self, id: usize, rect: Rectangle<WorldSpace>) {
+        self.unlink(id);
+        self.rects[id] = Rectangle::from_points_well_ordered(rect.p1, rect.p2);
+        self.link(id);
+    }
+
+    // Same semantics as `Vec::swap_remove`: the last object takes the place of the removed one.
+    pub fn swap_remove(&mut self, id: usize) {
+        let last = self.rects.len() - 1;
+        self.unlink(id);
+        if id != last {
+            self.unlink(last);
+            self.rects.swap_remove(id);
+            self.link(id);
+        }
+        else {
+            self.rects.pop();
+        }
+    }
+
+    // Returns the indices of the objects whose bounding rectangle intersects `rect`, in increasing order.
+    pub fn query_rect(&self, rect: Rectangle<WorldSpace>) -> Vec<usize> {
+        let rect = Rectangle::from_points_well_ordered(rect.p1, rect.p2);
+        let mut result = Vec::new();
+
+        match self.cell_range(rect) {
+            Some((x1, y1, x2, y2)) if Self::cell_count(x1, y1, x2, y2) <= self.cells.len() as i64 => {
+                for x in x1..=x2 {
+                    for y in y1..=y2 {
+                        if let Some(ids) = self.cells.get(&(x, y)) {
+                            result.extend_from_slice(ids);
+                        }
+                    }
+                }
+                result.extend_from_slice(&self.oversized);
+            },
+            _ => {
+                // The query covers more cells than there are objects in the grid,
+                // so it is cheaper to just check every object.
+                result.extend(0..self.rects.len());
+            },
+        }
+
+        result.sort_unstable();
+        result.dedup();
+        result.retain(|id| self.rects[*id].intersects(&rect));
+        result
+    }
+
+    fn link(&mut self, id: usize) {
+        if let Some((x1, y1, x2, y2)) = self.cell_range(self.rects[id]) {
+            if Self::cell_count(x1, y1, x2, y2) <= Self::MAX_CELLS_PER_OBJECT {
+                for x in x1..=x2 {
+                    for y in y1..=y2 {
+                        self.cells.entry((x, y)).or_default().push(id);
+                    }
+                }
+                return;
+            }
+        }
+        self.oversized.push(id);
+    }
+
+    fn unlink(&mut self, id: usize) {
+        if let Some((x1, y1, x2, y2)) = self.cell_range(self.rects[id]) {
+            if Self::cell_count(x1, y1, x2, y2) <= Self::MAX_CELLS_PER_OBJECT {
+                for x in x1..=x2 {
+                    for y in y1..=y2 {
+                        if let Some(ids) = self.cells.get_mut(&(x, y)) {
+                            ids.retain(|i| *i != id);
+                            if ids.is_empty() {
+                                self.cells.remove(&(x, y));
+                            }
+                        }
+                    }
+                }
+                return;
+            }
+        }
+        self.oversized.retain(|i| *i != id);
+    }
+
+    fn cell_range(&self, rect: Rectangle<WorldSpace>) -> Option<(i32, i32, i32, i32)> {
+        let coordinates = [rect.p1.x, rect.p1.y, rect.p2.x, rect.p2.y];
+        if coordinates.iter().any(|c| !c.is_finite() || c.abs() / self.cell_size > i32::MAX as f32) {
+            return None;
+        }
+        let [x1, y1, x2, y2] = coordinates.map(|c| (c / self.cell_size).floor() as i32);
+        if x1 > x2 || y1 > y2 {
+            return None;
+        }
+        Some((x1, y1, x2, y2))
+    }
+
+    fn cell_count(x1: i32, y1: i32, x2: i32, y2: i32) -> i64 {
+        (x2 as i64 - x1 as i64 + 1) * (y2 as i64 - y1 as i64 + 1)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x1: f32, y1: f32, x2: f32, y2: f32) -> Rectangle<WorldSpace> {
+        Rectangle { p1: Vector2::new(x1, y1), p2: Vector2::new(x2, y2) }
+    }
+
+    // what `query_rect` should return, found by checking every rectangle
+    fn brute_force(rects: &[Rectangle<WorldSpace>], query: Rectangle<WorldSpace>) -> Vec<usize> {
+        let query = Rectangle::from_points_well_ordered(query.p1, query.p2);
+        (0..rects.len()).filter(|i| Rectangle::from_points_well_ordered(rects[*i].p1, rects[*i].p2).intersects(&query)).collect()
+    }
+
+    #[test]
+    fn query_returns_sorted_unique_intersecting_objects() {
+        let mut index = SpatialIndex::new(10.0);
+        index.push(rect(0.0, 0.0, 25.0, 25.0));
+        index.push(rect(100.0, 100.0, 105.0, 105.0));
+        index.push(rect(5.0, 5.0, 15.0, 15.0));
+        index.push(rect(-30.0, -30.0, -20.0, -20.0));
+
+        assert_eq!(index.query_rect(rect(0.0, 0.0, 20.0, 20.0)), vec![0, 2]);
+        assert_eq!(index.query_rect(rect(-25.0, -25.0, 1.0, 1.0)), vec![0, 3]);
+        assert_eq!(index.query_rect(rect(50.0, 50.0, 60.0, 60.0)), Vec::<usize>::new());
+        // a query covering more cells than there are objects checks every object
+        assert_eq!(index.query_rect(rect(-1000.0, -1000.0, 1000.0, 1000.0)), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn rectangles_are_well_ordered() {
+        let mut index = SpatialIndex::new(10.0);
+        index.push(rect(25.0, 25.0, 0.0, 0.0));
+        assert_eq!(index.query_rect(rect(20.0, 20.0, 12.0, 12.0)), vec![0]);
+        assert_eq!(index.query_rect(rect(30.0, 30.0, 40.0, 40.0)), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn oversized_and_infinite_objects_are_always_checked() {
+        let mut index = SpatialIndex::new(1.0);
+        index.push(rect(0.0, 0.0, 1.0, 1.0));
+        index.push(rect(-5000.0, -5000.0, 5000.0, 5000.0));
+        index.push(rect(f32::NEG_INFINITY, 3.0, f32::INFINITY, 4.0));
+        assert_eq!(index.oversized, vec![1, 2]);
+
+        assert_eq!(index.query_rect(rect(0.5, 0.5, 0.6, 0.6)), vec![0, 1]);
+        assert_eq!(index.query_rect(rect(10.0, 3.5, 11.0, 3.6)), vec![1, 2]);
+
+        // shrinking an oversized object moves it into the grid
+        index.update(1, rect(10.0, 10.0, 11.0, 11.0));
+        assert_eq!(index.oversized, vec![2]);
+        assert_eq!(index.query_rect(rect(0.5, 0.5, 0.6, 0.6)), vec![0]);
+        assert_eq!(index.query_rect(rect(10.5, 10.5, 10.6, 10.6)), vec![1]);
+    }
+
+    #[test]
+    fn swap_remove_mirrors_vec() {
+        let mut rects = vec![
+            rect(0.0, 0.0, 10.0, 10.0),
+            rect(-5000.0, -5000.0, 5000.0, 5000.0),
+            rect(20.0, 0.0, 30.0, 10.0),
+            rect(5.0, 5.0, 25.0, 8.0),
+            rect(40.0, 40.0, 50.0, 50.0),
+        ];
+        let mut index = SpatialIndex::new(4.0);
+        for r in rects.iter() {
+            index.push(*r);
+        }
+
+        let queries = [
+            rect(0.0, 0.0, 1.0, 1.0),
+            rect(21.0, 1.0, 22.0, 2.0),
+            rect(45.0, 45.0, 46.0, 46.0),
+            rect(6.0, 6.0, 24.0, 7.0),
+            rect(-100.0, -100.0, 100.0, 100.0),
+        ];
+        for removed in [1, 0, 2, 1, 0] {
+            rects.swap_remove(removed);
+            index.swap_remove(removed);
+            for query in queries {
+                assert_eq!(index.query_rect(query), brute_force(&rects, query));
+            }
+        }
+        assert!(index.cells.is_empty());
+        assert!(index.oversized.is_empty());
+    }
+
+    #[test]
+    fn update_moves_objects_between_cells() {
+        let mut index = SpatialIndex::new(10.0);
+        index.push(rect(0.0, 0.0, 5.0, 5.0));
+        index.push(rect(0.0, 0.0, 5.0, 5.0));
+        index.update(0, rect(50.0, 50.0, 55.0, 55.0));
+        assert_eq!(index.query_rect(rect(1.0, 1.0, 2.0, 2.0)), vec![1]);
+        assert_eq!(index.query_rect(rect(51.0, 51.0, 52.0, 52.0)), vec![0]);
+    }
+}
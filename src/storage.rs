@@ -0,0 +1,28 @@
+use std::path::PathBuf;
+use crate::app::NAME;
+
+
+// Directory for the files the app keeps between sessions (palettes etc.), created if it doesn't exist yet.
+// Returns `None` when no suitable directory is known on this system.
+pub fn data_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    }
+    else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library").join("Application Support"))
+    }
+    else {
+        std::env::var_os("XDG_DATA_HOME").map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+    };
+
+    let dir = base?.join(NAME.to_lowercase());
+    std::fs::create_dir_all(&dir).ok()?;
+    Some(dir)
+}
+
+
+pub fn data_file(file_name: &str) -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(file_name))
+}
@@ -0,0 +1,174 @@
+use std::fmt::Write;
+use std::sync::Arc;
+use crate::primitives::*;
+use crate::engine::ScreenPainter;
+use crate::scene_file::base64_encode;
+
+
+// Writes what is drawn as an SVG document, e.g. for copying the selection to vector graphics programs.
+pub struct SvgPainter {
+    width: u32,
+    height: u32,
+    definitions: String,
+    elements: String,
+    gradient_count: usize,
+}
+
+impl SvgPainter {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            definitions: String::new(),
+            elements: String::new(),
+            gradient_count: 0,
+        }
+    }
+
+    pub fn finish(self) -> String {
+        let mut result = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n", self.width, self.height);
+        if !self.definitions.is_empty() {
+            result += &format!("<defs>\n{}</defs>\n", self.definitions);
+        }
+        result += &self.elements;
+        result += "</svg>\n";
+        result
+    }
+
+    // Adds a gradient to the definitions, its coordinates are relative to `frame` like in `Fill::color_at`.
+    // Returns the value of the fill attribute.
+    fn gradient(&mut self, fill: &Fill, frame: Rectangle<ScreenSpace>) -> String {
+        let id = format!("gradient{}", self.gradient_count);
+        self.gradient_count += 1;
+        let transform = format!("matrix({} 0 0 {} {} {})", number(frame.width().value), number(frame.height().value), number(frame.p1.x), number(frame.p1.y));
+        let (element, gradient) = match fill {
+            Fill::LinearGradient(gradient) => {
+                let _ = writeln!(self.definitions, "<linearGradient id=\"{}\" gradientUnits=\"userSpaceOnUse\" gradientTransform=\"{}\" x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\">",
+                                 id, transform, number(gradient.start.x), number(gradient.start.y), number(gradient.end.x), number(gradient.end.y));
+                ("linearGradient", gradient)
+            },
+            Fill::RadialGradient(gradient) => {
+                let _ = writeln!(self.definitions, "<radialGradient id=\"{}\" gradientUnits=\"userSpaceOnUse\" gradientTransform=\"{}\" cx=\"{}\" cy=\"{}\" r=\"{}\">",
+                                 id, transform, number(gradient.start.x), number(gradient.start.y), number((gradient.end - gradient.start).length()));
+                ("radialGradient", gradient)
+            },
+            _ => return String::from("none"),
+        };
+        for stop in gradient.stops.iter() {
+            let _ = writeln!(self.definitions, "<stop offset=\"{}\" stop-color=\"{}\" stop-opacity=\"{}\"/>", number(stop.offset), rgb(stop.color), opacity(stop.color));
+        }
+        let _ = writeln!(self.definitions, "</{}>", element);
+        format!("url(#{})", id)
+    }
+}
+
+impl ScreenPainter for SvgPainter {
+    type Texture = Arc<image::DynamicImage>;
+
+    fn draw_line(&mut self, start: Vector2<ScreenSpace>, end: Vector2<ScreenSpace>, stroke: Stroke<ScreenSpace>) {
+        self.draw_polyline(&[start, end], stroke);
+    }
+
+    fn draw_polyline(&mut self, points: &[Vector2<ScreenSpace>], stroke: Stroke<ScreenSpace>) {
+        let Some(first) = points.first() else {
+            return;
+        };
+        // a path instead of a polyline, zero length paths still get their caps drawn
+        let mut path = format!("M{},{}", number(first.x), number(first.y));
+        for p in points.iter().skip(1) {
+            let _ = write!(path, " L{},{}", number(p.x), number(p.y));
+        }
+        if points.len() == 1 {
+            path += " Z";
+        }
+        let _ = writeln!(self.elements, "<path d=\"{}\" fill=\"none\" {}/>", path, stroke_attributes(stroke));
+    }
+
+    fn draw_circle(&mut self, center: Vector2<ScreenSpace>, radius: Number<ScreenSpace>, stroke: Stroke<ScreenSpace>) {
+        let _ = writeln!(self.elements, "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" {}/>", number(center.x), number(center.y), number(radius.value), fill_attributes(stroke.color));
+    }
+
+    fn draw_rectangle(&mut self, rectangle: Rectangle<ScreenSpace>, stroke: Stroke<ScreenSpace>) {
+        let rect = Rectangle::from_points_well_ordered(rectangle.p1, rectangle.p2);
+        let _ = writeln!(self.elements, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" {}/>",
+                         number(rect.p1.x), number(rect.p1.y), number(rect.width().value), number(rect.height().value), stroke_attributes(stroke));
+    }
+
+    fn draw_rectangle_filled(&mut self, rectangle: Rectangle<ScreenSpace>, color: Color, stroke: Option<Stroke<ScreenSpace>>) {
+        let rect = Rectangle::from_points_well_ordered(rectangle.p1, rectangle.p2);
+        let _ = writeln!(self.elements, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {} {}/>",
+                         number(rect.p1.x), number(rect.p1.y), number(rect.width().value), number(rect.height().value),
+                         fill_attributes(color), stroke.map_or_else(String::new, stroke_attributes));
+    }
+
+    fn draw_polygon_filled(&mut self, points: &[Vector2<ScreenSpace>], fill: &Fill, frame: Rectangle<ScreenSpace>) {
+        let fill = match fill {
+            Fill::None         => return,
+            Fill::Solid(color) => fill_attributes(*color),
+            _                  => format!("fill=\"{}\"", self.gradient(fill, frame)),
+        };
+        let points = points.iter().map(|p| format!("{},{}", number(p.x), number(p.y))).collect::<Vec<_>>().join(" ");
+        let _ = writeln!(self.elements, "<polygon points=\"{}\" {} fill-rule=\"evenodd\"/>", points, fill);
+    }
+
+    fn load_image(&mut self, _name: &str, image: &Arc<image::DynamicImage>) -> Self::Texture {
+        Arc::clone(image)
+    }
+
+    fn draw_image(&mut self, frame: Rectangle<ScreenSpace>, texture: &Self::Texture, flip: Flip) {
+        // a picture that can't be encoded is left out, the rest of the drawing is still useful
+        let mut png = std::io::Cursor::new(Vec::new());
+        if texture.write_to(&mut png, image::ImageFormat::Png).is_err() {
+            return;
+        }
+        let frame = Rectangle::from_points_well_ordered(frame.p1, frame.p2);
+        // mirrors around the center of the frame
+        let (scale_x, translate_x) = if flip.horizontal { (-1.0, frame.p1.x + frame.p2.x) } else { (1.0, 0.0) };
+        let (scale_y, translate_y) = if flip.vertical { (-1.0, frame.p1.y + frame.p2.y) } else { (1.0, 0.0) };
+        let _ = writeln!(self.elements, "<image x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" preserveAspectRatio=\"none\" transform=\"matrix({} 0 0 {} {} {})\" xlink:href=\"data:image/png;base64,{}\"/>",
+                         number(frame.p1.x), number(frame.p1.y), number(frame.width().value), number(frame.height().value),
+                         scale_x, scale_y, number(translate_x), number(translate_y), base64_encode(png.get_ref()));
+    }
+}
+
+
+// SVG 1.1 doesn't understand "#rrggbbaa", so the alpha is written separately
+fn rgb(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue)
+}
+
+
+fn opacity(color: Color) -> String {
+    number(color.alpha as f32 / 255.0)
+}
+
+
+fn fill_attributes(color: Color) -> String {
+    format!("fill=\"{}\" fill-opacity=\"{}\"", rgb(color), opacity(color))
+}
+
+
+fn stroke_attributes(stroke: Stroke<ScreenSpace>) -> String {
+    let cap = match stroke.cap {
+        LineCap::Butt   => "butt",
+        LineCap::Round  => "round",
+        LineCap::Square => "square",
+    };
+    let join = match stroke.join {
+        LineJoin::Miter => "miter",
+        LineJoin::Round => "round",
+        LineJoin::Bevel => "bevel",
+    };
+    let mut attributes = format!("stroke=\"{}\" stroke-opacity=\"{}\" stroke-width=\"{}\" stroke-linecap=\"{}\" stroke-linejoin=\"{}\"",
+                                 rgb(stroke.color), opacity(stroke.color), number(stroke.thickness.value), cap, join);
+    if let Some(lengths) = stroke.dash.lengths(stroke.thickness) {
+        let _ = write!(attributes, " stroke-dasharray=\"{}\"", lengths.map(number).join(" "));
+    }
+    attributes
+}
+
+
+// rounded, so that the document doesn't fill up with digits nobody can see
+fn number(x: f32) -> String {
+    format!("{}", (x * 100.0).round() / 100.0)
+}
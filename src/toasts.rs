@@ -0,0 +1,200 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use eframe::egui;
+use crate::error::Error;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToastKind {
+    Info,
+    Warning,
+    Error,
+}
+
+impl ToastKind {
+    // errors stay longer, they usually need to be read
+    fn duration(self) -> Duration {
+        match self {
+            Self::Info    => Duration::from_secs(4),
+            Self::Warning => Duration::from_secs(8),
+            Self::Error   => Duration::from_secs(12),
+        }
+    }
+
+    fn color(self, visuals: &egui::Visuals) -> egui::Color32 {
+        match self {
+            Self::Info    => visuals.hyperlink_color,
+            Self::Warning => visuals.warn_fg_color,
+            Self::Error   => visuals.error_fg_color,
+        }
+    }
+}
+
+
+struct Toast {
+    kind: ToastKind,
+    title: String,
+    message: String,
+    // the underlying errors, only shown small
+    causes: Vec<String>,
+    hint: Option<&'static str>,
+    folder: Option<PathBuf>,
+    // restarted while the mouse is over the toast, so that it doesn't disappear while it's being read
+    shown_since: Instant,
+}
+
+
+// Non-modal notifications that stack up in the bottom right corner and disappear by themselves.
+#[derive(Default)]
+pub struct Toasts {
+    toasts: Vec<Toast>,
+}
+
+impl Toasts {
+    const MAX_VISIBLE: usize = 5;
+    const WIDTH: f32 = 280.0;
+    const MARGIN: f32 = 10.0;
+
+    pub fn info(&mut self, title: impl Into<String>) {
+        self.push(ToastKind::Info, title.into(), String::new());
+    }
+
+    pub fn warning(&mut self, title: impl Into<String>) {
+        self.push(ToastKind::Warning, title.into(), String::new());
+    }
+
+    // `title` says what the app was trying to do, the error what went wrong.
+    pub fn error(&mut self, title: impl Into<String>, error: &Error) {
+        self.push(ToastKind::Error, title.into(), error.to_string());
+        let toast = self.toasts.last_mut().unwrap();
+        let mut source = std::error::Error::source(error);
+        while let Some(cause) = source {
+            toast.causes.push(cause.to_string());
+            source = cause.source();
+        }
+        toast.hint = error.hint();
+        toast.folder = error.path().and_then(|path| path.parent()).map(|path| path.to_path_buf());
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let now = Instant::now();
+        self.toasts.retain(|toast| now.duration_since(toast.shown_since) < toast.kind.duration());
+        if self.toasts.is_empty() {
+            return;
+        }
+
+        let hidden_count = self.toasts.len().saturating_sub(Self::MAX_VISIBLE);
+        let mut to_be_closed = None;
+        egui::Area::new(egui::Id::new("toasts"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::Vec2::splat(-Self::MARGIN))
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                ui.set_width(Self::WIDTH);
+                if hidden_count > 0 {
+                    ui.weak(format!("+{} more", hidden_count));
+                }
+                // the newest one is at the bottom, closest to the corner
+                for (i, toast) in self.toasts.iter_mut().enumerate().skip(hidden_count) {
+                    let color = toast.kind.color(ui.visuals());
+                    let response = egui::Frame::popup(ui.style()).stroke(egui::Stroke::new(1.0, color)).show(ui, |ui| {
+                        ui.set_width(Self::WIDTH);
+                        ui.horizontal(|ui| {
+                            ui.add(egui::Label::new(egui::RichText::new(&toast.title).strong().color(color)).wrap());
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                                if ui.small_button("x").clicked() {
+                                    to_be_closed = Some(i);
+                                }
+                            });
+                        });
+                        if !toast.message.is_empty() {
+                            ui.label(&toast.message);
+                        }
+                        for cause in toast.causes.iter() {
+                            ui.small(format!("caused by: {}", cause));
+                        }
+                        if let Some(hint) = toast.hint {
+                            ui.label(egui::RichText::new(hint).italics());
+                        }
+                        if toast.kind == ToastKind::Error {
+                            ui.horizontal(|ui| {
+                                if ui.small_button("copy details").clicked() {
+                                    let mut details = format!("{}\n{}", toast.title, toast.message);
+                                    for cause in toast.causes.iter() {
+                                        details += &format!("\ncaused by: {}", cause);
+                                    }
+                                    ui.ctx().copy_text(details);
+                                }
+                                if let Some(folder) = &toast.folder {
+                                    if ui.small_button("open folder").on_hover_text(folder.display().to_string()).clicked() {
+                                        ui.ctx().open_url(egui::OpenUrl::new_tab(file_url(folder)));
+                                    }
+                                }
+                            });
+                        }
+                    }).response;
+                    if response.contains_pointer() {
+                        toast.shown_since = now;
+                    }
+                }
+            });
+        if let Some(i) = to_be_closed {
+            self.toasts.remove(i);
+        }
+
+        // wake up in time to remove the next toast that expires
+        if let Some(remaining) = self.toasts.iter().map(|toast| toast.kind.duration().saturating_sub(now.duration_since(toast.shown_since))).min() {
+            ctx.request_repaint_after(remaining);
+        }
+    }
+
+    fn push(&mut self, kind: ToastKind, title: String, message: String) {
+        self.toasts.push(Toast {
+            kind,
+            title,
+            message,
+            causes: Vec::new(),
+            hint: None,
+            folder: None,
+            shown_since: Instant::now(),
+        });
+    }
+}
+
+
+// file:// URL of an absolute path, with everything but plain path characters percent-encoded
+fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    let path = if cfg!(windows) { path.replace('\\', "/") } else { path.into_owned() };
+    let (mut url, path) = if let Some(unc_path) = path.strip_prefix("//") {
+        // the server of a UNC path is the host
+        (String::from("file://"), unc_path)
+    }
+    else if path.starts_with('/') {
+        (String::from("file://"), &path[..])
+    }
+    else {
+        // drive letter paths like C:/...
+        (String::from("file:///"), &path[..])
+    };
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => url.push(byte as char),
+            _ => url += &format!("%{:02X}", byte),
+        }
+    }
+    url
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_urls_are_percent_encoded() {
+        assert_eq!(file_url(Path::new("/home/me/my pictures/#1 100%")), "file:///home/me/my%20pictures/%231%20100%25");
+        assert_eq!(file_url(Path::new("/tmp/ünï")), "file:///tmp/%C3%BCn%C3%AF");
+        assert_eq!(file_url(Path::new("C:/Users/me/Pictures")), "file:///C:/Users/me/Pictures");
+        assert_eq!(file_url(Path::new("//server/share/a b")), "file://server/share/a%20b");
+    }
+}